@@ -0,0 +1,343 @@
+//! Analysis of rendered and recorded sounds.
+//!
+//! Sounds are cut into overlapping frames and described by their
+//! loudness envelope, pitch contour and spectral centroid, which is
+//! enough to tell how close two short effects sound. `Features`
+//! condenses the same measures into a few numbers per sound.
+
+pub mod fft;
+pub mod matching;
+
+use fft::{Complex, fft, ifft};
+use serde::{Deserialize, Serialize};
+
+use crate::synth::params::SynthParams;
+use crate::synth::{SAMPLE_RATE, Synth};
+
+/// Length of an analysis frame, in samples.
+pub const FRAME_SIZE: usize = 1024;
+/// Distance between the starts of two frames, in samples.
+pub const HOP_SIZE: usize = 512;
+
+/// Frames quieter than this, relative to the loudest one, are silent.
+const SILENCE_DB: f32 = -60.0;
+/// Lowest normalized autocorrelation peak of a pitched frame.
+const VOICING_THRESHOLD: f32 = 0.5;
+/// Shortest period considered by the pitch tracker, about 2.2kHz.
+const MIN_LAG: usize = 20;
+/// Pitch changes smaller than this, in semitones, count as flat.
+const FLAT_PITCH_RANGE: f32 = 1.0;
+
+/// Resamples with linear interpolation, to nothing when either rate is
+/// zero.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    if from == 0 || to == 0 {
+        return Vec::new();
+    }
+
+    let ratio = from as f64 / to as f64;
+    let length = (samples.len() as f64 / ratio) as usize;
+    (0..length)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fraction = (position - index as f64) as f32;
+            let a = samples[index];
+            let b = samples.get(index + 1).copied().unwrap_or(a);
+            a + (b - a) * fraction
+        })
+        .collect()
+}
+
+/// Frame by frame description of a sound at `SAMPLE_RATE`.
+#[derive(Clone, Default)]
+pub struct Profile {
+    /// Loudness of each frame in dB relative to the loudest frame.
+    pub envelope: Vec<f32>,
+    /// Fundamental frequency of each frame in Hz, zero when unpitched.
+    pub pitch: Vec<f32>,
+    /// Spectral centroid of each frame in Hz.
+    pub centroid: Vec<f32>,
+}
+
+impl Profile {
+    pub fn new(samples: &[f32]) -> Self {
+        let mut rms = Vec::new();
+        let mut pitch = Vec::new();
+        let mut centroid = Vec::new();
+
+        let window = hann_window();
+        for frame in frames(samples) {
+            rms.push(loudness(frame));
+
+            let spectrum = frame_spectrum(frame, &window);
+            centroid.push(spectral_centroid(&spectrum));
+            pitch.push(frame_pitch(frame));
+        }
+
+        let peak = rms.iter().copied().fold(0.0, f32::max).max(1e-9);
+        let envelope = rms
+            .iter()
+            .map(|&value| (20.0 * (value / peak).max(1e-6).log10()).max(SILENCE_DB))
+            .collect();
+
+        Profile {
+            envelope,
+            pitch,
+            centroid,
+        }
+    }
+
+    /// Rough perceptual distance between two sounds, zero when they
+    /// match. Combines the loudness envelopes, the pitch contours in
+    /// octaves and the spectral centroids in octaves.
+    pub fn distance(&self, other: &Profile) -> f32 {
+        let frames = self.envelope.len().max(other.envelope.len());
+        if frames == 0 {
+            return 0.0;
+        }
+
+        let mut envelope = 0.0;
+        let mut pitch = 0.0;
+        let mut centroid = 0.0;
+        let mut audible = 0;
+        for i in 0..frames {
+            let a = self.envelope.get(i).copied().unwrap_or(SILENCE_DB);
+            let b = other.envelope.get(i).copied().unwrap_or(SILENCE_DB);
+            envelope += (a - b).abs() / -SILENCE_DB;
+
+            if a <= SILENCE_DB || b <= SILENCE_DB {
+                continue;
+            }
+            audible += 1;
+
+            let (pa, pb) = (self.pitch[i], other.pitch[i]);
+            pitch += match (pa > 0.0, pb > 0.0) {
+                (true, true) => (pa / pb).log2().abs().min(2.0),
+                (false, false) => 0.0,
+                _ => 1.0,
+            };
+
+            let (ca, cb) = (self.centroid[i].max(1.0), other.centroid[i].max(1.0));
+            centroid += (ca / cb).log2().abs().min(4.0);
+        }
+
+        let audible = audible.max(1) as f32;
+        envelope / frames as f32 + pitch / audible + 0.5 * centroid / audible
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PitchDirection {
+    Rising,
+    Falling,
+    Flat,
+    Unpitched,
+}
+
+/// Summary of a sound, handy to sort and filter generated effects.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Features {
+    /// Length of the sound in seconds.
+    pub duration: f32,
+    /// Time to reach 90% of the peak loudness, in seconds.
+    pub attack_time: f32,
+    /// Largest absolute sample value.
+    pub peak: f32,
+    /// Root mean square of the whole sound.
+    pub rms: f32,
+    /// Loudness-weighted average of the spectral centroid, in Hz.
+    pub spectral_centroid: f32,
+    /// Pitch of the first pitched frame, in Hz.
+    pub pitch_start: Option<f32>,
+    /// Pitch of the last pitched frame, in Hz.
+    pub pitch_end: Option<f32>,
+    pub pitch_direction: PitchDirection,
+    /// Loudness-weighted spectral flatness, from 0 for a pure tone to 1
+    /// for white noise.
+    pub noisiness: f32,
+    /// Sign changes per second.
+    pub zero_crossing_rate: f32,
+}
+
+impl Features {
+    /// Extracts the features of a sound sampled at `SAMPLE_RATE`.
+    pub fn new(samples: &[f32]) -> Self {
+        let duration = samples.len() as f32 / SAMPLE_RATE as f32;
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count();
+        let zero_crossing_rate = if duration > 0.0 {
+            crossings as f32 / duration
+        } else {
+            0.0
+        };
+
+        let window = hann_window();
+        let mut frame_rms = Vec::new();
+        let mut centroid = 0.0;
+        let mut flatness = 0.0;
+        let mut weight = 0.0;
+        let mut pitches = Vec::new();
+        for frame in frames(samples) {
+            let loudness = loudness(frame);
+            frame_rms.push(loudness);
+
+            let spectrum = frame_spectrum(frame, &window);
+            centroid += spectral_centroid(&spectrum) * loudness;
+            flatness += spectral_flatness(&spectrum) * loudness;
+            weight += loudness;
+
+            let pitch = frame_pitch(frame);
+            if pitch > 0.0 {
+                pitches.push(pitch);
+            }
+        }
+        if weight > 0.0 {
+            centroid /= weight;
+            flatness /= weight;
+        }
+
+        // the attack ends at the first frame close to the loudest one
+        let loudest = frame_rms.iter().copied().fold(0.0, f32::max);
+        let attack_frame = frame_rms
+            .iter()
+            .position(|&value| value >= loudest * 0.9)
+            .unwrap_or(0);
+        let attack_time = (attack_frame * HOP_SIZE) as f32 / SAMPLE_RATE as f32;
+
+        let pitch_start = pitches.first().copied();
+        let pitch_end = pitches.last().copied();
+        let pitch_direction = match (pitch_start, pitch_end) {
+            (Some(first), Some(last)) => {
+                let semitones = 12.0 * (last / first).log2();
+                if semitones > FLAT_PITCH_RANGE {
+                    PitchDirection::Rising
+                } else if semitones < -FLAT_PITCH_RANGE {
+                    PitchDirection::Falling
+                } else {
+                    PitchDirection::Flat
+                }
+            }
+            _ => PitchDirection::Unpitched,
+        };
+
+        Features {
+            duration,
+            attack_time,
+            peak,
+            rms,
+            spectral_centroid: centroid,
+            pitch_start,
+            pitch_end,
+            pitch_direction,
+            noisiness: flatness,
+            zero_crossing_rate,
+        }
+    }
+
+    /// Renders the sound and extracts its features.
+    pub fn from_params(params: &SynthParams) -> Self {
+        Features::new(&Synth::new(*params).render())
+    }
+}
+
+fn hann_window() -> Vec<f32> {
+    (0..FRAME_SIZE)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FRAME_SIZE as f32).cos())
+        .collect()
+}
+
+/// Frames of `FRAME_SIZE` samples every `HOP_SIZE`, the last ones cut
+/// short by the end of the sound.
+fn frames(samples: &[f32]) -> impl Iterator<Item = &[f32]> {
+    (0..samples.len())
+        .step_by(HOP_SIZE)
+        .map(|start| &samples[start..(start + FRAME_SIZE).min(samples.len())])
+}
+
+/// Loudness of a frame, short frames counting as padded with silence.
+fn loudness(frame: &[f32]) -> f32 {
+    (frame.iter().map(|s| s * s).sum::<f32>() / FRAME_SIZE as f32).sqrt()
+}
+
+/// Magnitude spectrum of a windowed frame, up to the Nyquist frequency.
+fn frame_spectrum(frame: &[f32], window: &[f32]) -> Vec<f32> {
+    let mut buffer = vec![Complex::default(); FRAME_SIZE];
+    for (i, &sample) in frame.iter().enumerate() {
+        buffer[i].re = sample * window[i];
+    }
+    fft(&mut buffer);
+
+    buffer[..FRAME_SIZE / 2].iter().map(Complex::norm).collect()
+}
+
+fn spectral_centroid(spectrum: &[f32]) -> f32 {
+    let bin_width = SAMPLE_RATE as f32 / FRAME_SIZE as f32;
+    let total: f32 = spectrum.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    let weighted: f32 = spectrum
+        .iter()
+        .enumerate()
+        .map(|(bin, magnitude)| bin as f32 * bin_width * magnitude)
+        .sum();
+    weighted / total
+}
+
+/// Ratio between the geometric and the arithmetic mean of the power
+/// spectrum.
+fn spectral_flatness(spectrum: &[f32]) -> f32 {
+    let power: Vec<f32> = spectrum.iter().map(|m| m * m + 1e-12).collect();
+    let arithmetic = power.iter().sum::<f32>() / power.len() as f32;
+    let geometric = (power.iter().map(|p| p.ln()).sum::<f32>() / power.len() as f32).exp();
+    (geometric / arithmetic).clamp(0.0, 1.0)
+}
+
+/// Pitch of a frame from the highest peak of its autocorrelation,
+/// computed through the power spectrum of the zero-padded frame.
+fn frame_pitch(frame: &[f32]) -> f32 {
+    let size = FRAME_SIZE * 2;
+    let mut buffer = vec![Complex::default(); size];
+    for (i, &sample) in frame.iter().enumerate() {
+        buffer[i].re = sample;
+    }
+    fft(&mut buffer);
+    for value in buffer.iter_mut() {
+        *value = Complex::new(value.norm_sqr(), 0.0);
+    }
+    ifft(&mut buffer);
+
+    let energy = buffer[0].re;
+    if energy <= 1e-9 {
+        return 0.0;
+    }
+
+    // skip the main lobe around lag zero before looking for the peak
+    let max_lag = FRAME_SIZE / 2;
+    let mut lag = MIN_LAG;
+    while lag < max_lag && buffer[lag].re > 0.0 {
+        lag += 1;
+    }
+
+    let best = (lag..max_lag).max_by(|&a, &b| buffer[a].re.total_cmp(&buffer[b].re));
+    match best {
+        Some(best) if buffer[best].re / energy >= VOICING_THRESHOLD => {
+            SAMPLE_RATE as f32 / best as f32
+        }
+        _ => 0.0,
+    }
+}
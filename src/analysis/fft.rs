@@ -0,0 +1,109 @@
+//! Radix-2 fast Fourier transform.
+
+use std::f32::consts::TAU;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.norm_sqr().sqrt()
+    }
+
+    pub fn norm_sqr(&self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    pub fn conj(&self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+/// Forward transform in place. The length must be a power of two.
+pub fn fft(buffer: &mut [Complex]) {
+    transform(buffer, false);
+}
+
+/// Inverse transform in place, scaled so that `ifft(fft(x)) == x`.
+/// The length must be a power of two.
+pub fn ifft(buffer: &mut [Complex]) {
+    transform(buffer, true);
+
+    let scale = 1.0 / buffer.len() as f32;
+    for value in buffer.iter_mut() {
+        value.re *= scale;
+        value.im *= scale;
+    }
+}
+
+fn transform(buffer: &mut [Complex], inverse: bool) {
+    let n = buffer.len();
+    assert!(n.is_power_of_two(), "FFT length must be a power of two");
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut length = 2;
+    while length <= n {
+        let angle = sign * TAU / length as f32;
+        let step = Complex::new(angle.cos(), angle.sin());
+        for start in (0..n).step_by(length) {
+            let mut w = Complex::new(1.0, 0.0);
+            for k in 0..length / 2 {
+                let even = buffer[start + k];
+                let odd = buffer[start + k + length / 2] * w;
+                buffer[start + k] = even + odd;
+                buffer[start + k + length / 2] = even - odd;
+                w = w * step;
+            }
+        }
+        length <<= 1;
+    }
+}
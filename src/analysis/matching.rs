@@ -0,0 +1,123 @@
+//! Search for the parameters that best imitate a reference sound.
+//!
+//! For every wave type a few random sounds are drawn from the preset
+//! generators and then refined by mutation, keeping whatever lowers the
+//! distance between their profile and the profile of the target.
+
+use rand::prelude::*;
+
+use super::Profile;
+use crate::synth::Synth;
+use crate::synth::params::{SynthParams, WaveType};
+use crate::synth::presets::{SoundType, SynthPreset};
+
+/// Longest candidate rendered during the search, in samples. Longer
+/// sounds are cut, which keeps hopeless candidates cheap.
+const MAX_RENDER_LENGTH: usize = 5 * crate::synth::SAMPLE_RATE as usize;
+
+#[derive(Clone, Copy)]
+pub struct MatchOptions {
+    /// Random starting sounds drawn for each wave type.
+    pub population: usize,
+    /// Refinement steps for each wave type.
+    pub iterations: usize,
+    /// Best candidates kept for each wave type.
+    pub results: usize,
+    pub seed: u64,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            population: 16,
+            iterations: 200,
+            results: 3,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MatchResult {
+    pub params: SynthParams,
+    /// Distance from the target, see `Profile::distance`.
+    pub distance: f32,
+}
+
+/// Searches the parameters of every wave type for the sounds closest
+/// to `target`, sampled at `SAMPLE_RATE`. Returns the best candidates
+/// of all wave types, closest first.
+pub fn match_sound(target: &[f32], options: &MatchOptions) -> Vec<MatchResult> {
+    let target = Profile::new(target);
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut preset = SynthPreset::with_seed(rng.random());
+    let mut synth = Synth::new(SynthParams::default());
+
+    let waves = [
+        WaveType::Square,
+        WaveType::Sawtooth,
+        WaveType::Sine,
+        WaveType::Noise,
+    ];
+    let generators = [
+        SoundType::PickupCoin,
+        SoundType::LaserShoot,
+        SoundType::Explosion,
+        SoundType::PowerUp,
+        SoundType::HitHurt,
+        SoundType::Jump,
+        SoundType::BlipSelect,
+        SoundType::Randomize,
+    ];
+
+    let mut results = Vec::new();
+    for wave_type in waves {
+        let mut best: Vec<MatchResult> = Vec::new();
+        let mut evaluate = |params: SynthParams, best: &mut Vec<MatchResult>| {
+            let distance = distance(&mut synth, &params, &target);
+            best.push(MatchResult { params, distance });
+            best.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            best.truncate(options.results.max(1));
+        };
+
+        for i in 0..options.population {
+            let mut params = preset.generate(generators[i % generators.len()]);
+            params.wave_type = wave_type;
+            params.clamp_ranges();
+            evaluate(params, &mut best);
+        }
+
+        for i in 0..options.iterations {
+            let Some(parent) = best.choose(&mut rng) else {
+                break;
+            };
+
+            // take smaller steps as the search settles
+            let progress = i as f32 / options.iterations as f32;
+            let mut params = parent.params;
+            params.mutate_with(&mut rng, 0.3, 0.2 - 0.18 * progress);
+            params.clamp_ranges();
+            evaluate(params, &mut best);
+        }
+
+        results.extend(best);
+    }
+
+    results.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    results
+}
+
+fn distance(synth: &mut Synth, params: &SynthParams, target: &Profile) -> f32 {
+    synth.set_params(*params);
+    synth.play_sample();
+
+    let mut data = Vec::new();
+    while let Some(value) = synth.synth_sample() {
+        data.push(value);
+        if data.len() >= MAX_RENDER_LENGTH {
+            break;
+        }
+    }
+
+    Profile::new(&data).distance(target)
+}
@@ -0,0 +1,131 @@
+use std::ops::RangeInclusive;
+
+use eframe::egui::{self, Color32, Pos2, Sense, Stroke, StrokeKind, pos2, vec2};
+use refexer::synth::envelope::{Breakpoint, Envelope};
+
+/// Distance in pixels within which a click grabs a breakpoint.
+const GRAB_RADIUS: f32 = 10.0;
+
+/// Breakpoint editor: drag points around, double-click to add one.
+pub struct EnvelopeEditor {
+    /// Index of the point shown in the curve slider
+    selected: Option<usize>,
+    /// Length of the time axis in seconds, frozen while dragging
+    time_scale: f32,
+}
+
+impl Default for EnvelopeEditor {
+    fn default() -> Self {
+        EnvelopeEditor {
+            selected: None,
+            time_scale: 1.0,
+        }
+    }
+}
+
+impl EnvelopeEditor {
+    /// Shows the editor, returns whether the envelope changed.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        envelope: &mut Envelope,
+        levels: RangeInclusive<f32>,
+    ) -> bool {
+        let (min, max) = (*levels.start(), *levels.end());
+        let (response, painter) = ui.allocate_painter(vec2(200.0, 100.0), Sense::click_and_drag());
+        let rect = response.rect.shrink(4.0);
+
+        if !response.dragged() {
+            self.time_scale = (envelope.duration() * 1.25).max(0.1);
+        }
+        let time_scale = self.time_scale;
+        let to_screen = |point: &Breakpoint| {
+            pos2(
+                rect.left() + point.time / time_scale * rect.width(),
+                rect.bottom() - (point.level - min) / (max - min) * rect.height(),
+            )
+        };
+        let from_screen = |pos: Pos2| {
+            let time = (pos.x - rect.left()) / rect.width() * time_scale;
+            let level = min + (rect.bottom() - pos.y) / rect.height() * (max - min);
+            (time.max(0.0), level.clamp(min, max))
+        };
+        let nearest = |envelope: &Envelope, pos: Pos2| {
+            envelope
+                .points()
+                .iter()
+                .map(|point| to_screen(point).distance(pos))
+                .enumerate()
+                .filter(|&(_, distance)| distance < GRAB_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(index, _)| index)
+        };
+
+        let mut changed = false;
+        if let Some(pos) = response.interact_pointer_pos() {
+            if response.double_clicked() {
+                let (time, level) = from_screen(pos);
+                if let Some(index) = envelope.insert(Breakpoint::new(time, level, 0.0)) {
+                    self.selected = Some(index);
+                    changed = true;
+                }
+            } else if response.drag_started() || response.clicked() {
+                self.selected = nearest(envelope, pos);
+            } else if response.dragged()
+                && let Some(index) = self.selected
+            {
+                let (time, level) = from_screen(pos);
+                let points = envelope.points_mut();
+                // keep the points in time order
+                let earliest = index.checked_sub(1).map_or(0.0, |i| points[i].time);
+                let latest = points.get(index + 1).map_or(f32::MAX, |p| p.time);
+                points[index].time = time.clamp(earliest, latest);
+                points[index].level = level;
+                changed = true;
+            }
+        }
+
+        // background, zero line and curve
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+        painter.rect_stroke(rect, 2.0, visuals.window_stroke, StrokeKind::Outside);
+        if min < 0.0 {
+            let zero = to_screen(&Breakpoint::new(0.0, 0.0, 0.0)).y;
+            painter.hline(rect.x_range(), zero, visuals.window_stroke);
+        }
+
+        let curve: Vec<Pos2> = (0..=rect.width() as usize)
+            .map(|x| {
+                let time = x as f32 / rect.width() * time_scale;
+                to_screen(&Breakpoint::new(time, envelope.level(time), 0.0))
+            })
+            .collect();
+        painter.line(curve, Stroke::new(1.5, visuals.text_color()));
+
+        for (i, point) in envelope.points().iter().enumerate() {
+            let color = if self.selected == Some(i) {
+                Color32::ORANGE
+            } else {
+                visuals.text_color()
+            };
+            painter.circle_filled(to_screen(point), 4.0, color);
+        }
+
+        // the curve of a segment belongs to the point it ends on
+        if let Some(index) = self.selected.filter(|&i| i < envelope.points().len()) {
+            let point = &mut envelope.points_mut()[index];
+            changed |= ui
+                .add(egui::Slider::new(&mut point.curve, -8.0..=8.0).text("Curve"))
+                .changed();
+            if ui.button("Remove point").clicked() {
+                envelope.remove(index);
+                self.selected = None;
+                changed = true;
+            }
+        } else {
+            ui.label("Double-click to add a point");
+        }
+
+        changed
+    }
+}
@@ -4,16 +4,39 @@
 //! video games.
 
 use rand::prelude::*;
+use std::ops::Range;
+use std::sync::Arc;
 use std::sync::mpsc::Sender;
 
 use eframe::egui::{self, Layout, Response, RichText, Slider, vec2};
+use refexer::analysis::Features;
+use refexer::export::code::{self, CodeForm, CodeOptions, MIN_RATE, SampleFormat, Source};
+use refexer::export::wav;
+use refexer::sound::SynthCommand;
 use refexer::synth::{
-    Synth,
-    params::SynthParams,
+    SAMPLE_RATE, Synth,
+    arpeggio::Arpeggio,
+    chip::Chip,
+    cleanup::{CleanupOptions, cleanup, cleanup_stereo},
+    convolution::{ConvolutionOptions, ImpulseResponse, convolve, convolve_stereo},
+    effects::svf::{FilterMode, FilterParams, MAX_CUTOFF, MIN_CUTOFF},
+    envelope::Envelope,
+    evolve::{Evolution, EvolveOptions},
+    fm::{MAX_INDEX, MAX_RATIO, RingModParams},
+    layered::{Layer, LayeredSound},
+    lfo::{LfoShape, LfoTarget},
+    live::LiveParam,
+    looping::{LoopRegion, find_loop},
+    params::{SynthParams, WaveType},
+    pitch::{NOTE_NAMES, Note, Scale},
     presets::{SoundType, SynthPreset},
+    sample::{Sample, SampleMode},
+    wavetable::{Cycle, MAX_POINTS, MIN_POINTS, Wavetable},
 };
 
+use super::envelope::EnvelopeEditor;
 use super::plot;
+use super::wavetable::{Edit, WavetableEditor};
 
 /// Sound button configuration
 const SOUND_BUTTONS: &[(&str, SoundType)] = &[
@@ -24,6 +47,7 @@ const SOUND_BUTTONS: &[(&str, SoundType)] = &[
     ("Hit / Hurt", SoundType::HitHurt),
     ("Jump", SoundType::Jump),
     ("Blip / Select", SoundType::BlipSelect),
+    ("Bell", SoundType::Bell),
 ];
 
 /// Main application state.
@@ -33,32 +57,137 @@ pub struct RefexerApp {
     /// Current parameters
     params: SynthParams,
     /// Channel sender for streaming audio data to the playback thread.
-    sender: Sender<Vec<f32>>,
+    sender: Sender<Vec<[f32; 2]>>,
+    /// Channel sender for the commands of the live synth.
+    live_sender: Sender<SynthCommand>,
+    /// Play single sounds on the live synth, which the live sliders
+    /// change while they play
+    live: bool,
     /// Random preset generator.
     preset: SynthPreset,
     /// inner plot data
     waveform_plot: plot::WaveformPlot,
     /// Random generator
     rng: StdRng,
+    /// Sound stored in the A slot of the morph
+    morph_a: Option<SynthParams>,
+    /// Sound stored in the B slot of the morph
+    morph_b: Option<SynthParams>,
+    /// Position of the morph between A and B
+    morph: f32,
+    /// Population of the breed and evolve mode
+    evolution: Evolution,
+    /// Seed of the current evolution session
+    evolve_seed: u64,
+    /// Whether rendered sounds go through the cleanup pass
+    clean: bool,
+    /// Settings of the cleanup pass
+    cleanup_options: CleanupOptions,
+    /// Whether to look for a loop and preview it
+    looping: bool,
+    /// Loop the sound between `loop_points` instead of searching one
+    custom_loop: bool,
+    /// Start and end of the custom loop, in seconds
+    loop_points: [f32; 2],
+    /// Loop found in the last rendered sound
+    loop_region: Option<LoopRegion>,
+    /// Sustain of the last rendered sound, where loops are searched
+    sustain: Range<usize>,
+    /// Last rendered sound, kept for export
+    sound: Vec<f32>,
+    /// Features of the last rendered sound
+    features: Option<Features>,
+    /// Destination of the WAV export
+    export_path: String,
+    /// Target of "Export as code", a C header or a Rust file
+    code_path: String,
+    code_options: CodeOptions,
+    /// Export the params as a Rust constant instead of the samples
+    code_params: bool,
+    /// Whether the export is convolved with the impulse response
+    convolve: bool,
+    /// Impulse response WAV used by the export
+    ir_path: String,
+    /// Wet/dry and pre-delay of the convolution
+    convolution: ConvolutionOptions,
+    /// Canvas of the wavetable oscillator
+    wavetable_editor: WavetableEditor,
+    /// Single-cycle WAV imported into the wavetable
+    wavetable_path: String,
+    /// WAV played by the sample wave type
+    sample_path: String,
+    /// Editors of the custom volume, pitch and filter envelopes
+    envelope_editors: [EnvelopeEditor; 3],
+    /// Whether the start frequency snaps to the notes of `scale`
+    snap_pitch: bool,
+    scale: Scale,
+    /// Root of the scale, 0 is C
+    scale_root: i32,
+    /// Text of the arpeggio pattern being edited
+    arp_pattern: String,
+    /// Tracks played together instead of `params` when not empty
+    layered: LayeredSound,
+    /// Track edited by the parameter sliders
+    selected_layer: usize,
+    /// Stereo mix of the last rendered layered sound, kept for export
+    layered_sound: Option<Vec<[f32; 2]>>,
+    /// File the layers are saved to and loaded from
+    layers_path: String,
 }
 
 impl RefexerApp {
-    pub fn new(sender: Sender<Vec<f32>>) -> Self {
+    pub fn new(sender: Sender<Vec<[f32; 2]>>, live_sender: Sender<SynthCommand>) -> Self {
         let params = SynthParams::default();
         let synth = Synth::new(params);
 
         RefexerApp {
             params,
             sender,
+            live_sender,
+            live: false,
             synth,
             preset: SynthPreset::new(),
             waveform_plot: Default::default(),
             rng: StdRng::from_os_rng(),
+            morph_a: None,
+            morph_b: None,
+            morph: 0.0,
+            evolution: Evolution::new(EvolveOptions::default(), 0),
+            evolve_seed: 0,
+            clean: false,
+            cleanup_options: CleanupOptions::default(),
+            looping: false,
+            custom_loop: false,
+            loop_points: [0.1, 0.3],
+            loop_region: None,
+            sustain: 0..0,
+            sound: Vec::new(),
+            features: None,
+            export_path: String::from("sound.wav"),
+            code_path: String::from("sound.h"),
+            code_options: CodeOptions::default(),
+            code_params: false,
+            convolve: false,
+            ir_path: String::from("ir.wav"),
+            convolution: ConvolutionOptions::default(),
+            wavetable_editor: Default::default(),
+            wavetable_path: String::from("cycle.wav"),
+            sample_path: String::from("sample.wav"),
+            envelope_editors: Default::default(),
+            snap_pitch: false,
+            scale: Scale::default(),
+            scale_root: 0,
+            arp_pattern: String::new(),
+            layered: LayeredSound::new(),
+            selected_layer: 0,
+            layered_sound: None,
+            layers_path: String::from("sound.json"),
         }
     }
 
     /// Plays a sound effect for the given type.
     fn play_sound(&mut self, sound_type: SoundType) {
+        self.preset.set_chip(self.params.chip);
         self.params = self.preset.generate(sound_type);
 
         self.play();
@@ -72,18 +201,134 @@ impl RefexerApp {
     }
 
     fn play(&mut self) {
+        if !self.layered.layers.is_empty() {
+            self.play_layered();
+            return;
+        }
+        let data = self.render();
+
+        if self.live {
+            self.play_live();
+        } else {
+            self.send(data.iter().map(|&sample| [sample, sample]).collect());
+        }
+    }
+
+    /// Renders the current sound for the plot and the exports, returns
+    /// what plays: the sound, or its loop repeated.
+    fn render(&mut self) -> Vec<f32> {
+        self.layered_sound = None;
         self.synth.set_params(self.params);
-        self.synth.play_sample();
+        let repeat_fade = if self.clean {
+            self.cleanup_options.repeat_fade
+        } else {
+            0.0
+        };
+        self.synth.set_repeat_fade(repeat_fade);
 
-        // add sound generation and tx.send
-        let mut data = Vec::new();
-        while let Some(value) = self.synth.synth_sample() {
-            data.push(value);
+        let mut data = self.synth.render();
+        let mut sustain = self.synth.sustain_range();
+        if self.clean {
+            let trimmed = cleanup(&mut data, &self.cleanup_options);
+            sustain = sustain.start.saturating_sub(trimmed)..sustain.end.saturating_sub(trimmed);
         }
 
         self.waveform_plot.set_data(&data);
+        self.sound.clone_from(&data);
+        self.features = Some(Features::new(&data));
+        self.sustain = sustain.clone();
+
+        // preview the loop by repeating it for a couple of seconds
+        self.loop_region = None;
+        if self.looping {
+            self.loop_region = if self.custom_loop {
+                let [start, end] = self
+                    .loop_points
+                    .map(|secs| (secs * SAMPLE_RATE as f32) as usize);
+                LoopRegion::new(start, end.min(data.len()))
+            } else {
+                find_loop(&data, sustain)
+            };
+            if let Some(region) = self.loop_region {
+                let repeats = 2 * SAMPLE_RATE as usize / region.len();
+                data = region.unroll(&data, repeats.clamp(1, 64));
+            }
+        }
+
+        data
+    }
+
+    /// Starts the current sound on the live synth, in place of the
+    /// rendered one. Cleanup and loops don't apply to it.
+    fn play_live(&mut self) {
+        if let Err(e) = self.sender.send(Vec::new()) {
+            eprintln!("Failed to send audio data: {}", e);
+        }
+        self.send_live(SynthCommand::Sources(
+            self.synth.sample().cloned(),
+            self.synth.cycle().cloned(),
+        ));
+        self.send_live(SynthCommand::Play(Box::new(self.params)));
+    }
+
+    /// Follows a change of a live slider. The sound playing on the live
+    /// synth glides to the new value, otherwise the sound plays again.
+    fn live_changed(&mut self, param: LiveParam) {
+        // the state-variable filter ignores the classic cutoffs
+        let filter = matches!(param, LiveParam::LpfFreq | LiveParam::HpfFreq)
+            && self.params.filter.is_some();
+        if !self.live || !self.layered.layers.is_empty() || filter {
+            self.play();
+            return;
+        }
+
+        self.render();
+        let value = self.synth.live_value(param);
+        self.send_live(SynthCommand::SetLive(param, value));
+    }
+
+    fn send_live(&self, command: SynthCommand) {
+        if let Err(e) = self.live_sender.send(command) {
+            eprintln!("Failed to send live command: {}", e);
+        }
+    }
+
+    /// Renders the current sound into the waveform plot without
+    /// playing it, for quick feedback while editing.
+    fn preview(&mut self) {
+        self.synth.set_params(self.params);
+        let data = self.synth.render();
+        self.waveform_plot.set_data(&data);
+    }
+
+    /// Plays every track together, after storing the edited parameters
+    /// in the selected track. The mix goes through the cleanup pass, but
+    /// repeat fades and loops only apply to single sounds.
+    fn play_layered(&mut self) {
+        if let Some(layer) = self.layered.layers.get_mut(self.selected_layer) {
+            layer.params = self.params;
+            layer.sample = self.synth.sample().cloned();
+            layer.cycle = self.synth.cycle().cloned();
+        }
+
+        let mut frames = self.layered.render_stereo();
+        if self.clean {
+            cleanup_stereo(&mut frames, &self.cleanup_options);
+        }
+        let mono: Vec<f32> = frames.iter().map(|[l, r]| (l + r) * 0.5).collect();
+        self.waveform_plot.set_data(&mono);
+        self.features = Some(Features::new(&mono));
+        self.sound = mono;
+        self.loop_region = None;
+        self.layered_sound = Some(frames.clone());
+
+        self.send(frames);
+    }
 
-        if let Err(e) = self.sender.send(data) {
+    /// Plays rendered frames, stopping the live synth.
+    fn send(&self, frames: Vec<[f32; 2]>) {
+        self.send_live(SynthCommand::Stop);
+        if let Err(e) = self.sender.send(frames) {
             eprintln!("Failed to send audio data: {}", e);
         }
     }
@@ -99,6 +344,608 @@ impl RefexerApp {
         }
     }
 
+    fn morph(&mut self, ui: &mut egui::Ui) {
+        ui.label("Morph");
+        ui.horizontal(|ui| {
+            if ui.button("Store A").clicked() {
+                self.morph_a = Some(self.params);
+            }
+            if ui.button("Store B").clicked() {
+                self.morph_b = Some(self.params);
+            }
+        });
+
+        if let (Some(a), Some(b)) = (self.morph_a, self.morph_b)
+            && slider(ui, "A / B", &mut self.morph, 0.0, 1.0).changed()
+        {
+            self.params = SynthParams::lerp(&a, &b, self.morph);
+            self.play();
+        }
+    }
+
+    fn evolve(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Seed");
+            ui.add(egui::DragValue::new(&mut self.evolve_seed));
+            if ui.button("New session").clicked() {
+                self.evolution = Evolution::new(self.evolution.options, self.evolve_seed);
+            }
+            if ui.button("From current").clicked() {
+                self.evolution =
+                    Evolution::from_params(self.evolution.options, self.evolve_seed, self.params);
+            }
+        });
+
+        let options = &mut self.evolution.options;
+        slider(ui, "Mutation rate", &mut options.mutation_rate, 0.0, 1.0);
+        slider(
+            ui,
+            "Mutation strength",
+            &mut options.mutation_strength,
+            0.0,
+            0.5,
+        );
+        ui.add(Slider::new(&mut options.elitism, 0..=options.population).text("Elitism"));
+
+        let mut played = None;
+        egui::Grid::new("evolve_grid").show(ui, |ui| {
+            for (i, candidate) in self.evolution.candidates.iter_mut().enumerate() {
+                ui.vertical(|ui| {
+                    if ui.button(format!("Play #{}", i + 1)).clicked() {
+                        played = Some(candidate.params);
+                    }
+                    ui.add(
+                        Slider::new(&mut candidate.fitness, 0.0..=5.0)
+                            .step_by(1.0)
+                            .text("Rating"),
+                    );
+                });
+                if i % 3 == 2 {
+                    ui.end_row();
+                }
+            }
+        });
+        if let Some(params) = played {
+            self.params = params;
+            self.play();
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Breed").clicked() {
+                self.evolution.next_generation();
+            }
+            ui.label(format!("Generation {}", self.evolution.generation()));
+        });
+    }
+
+    fn info(&self, ui: &mut egui::Ui) {
+        let Some(features) = &self.features else {
+            ui.label("No sound yet");
+            return;
+        };
+
+        let pitch = |pitch: Option<f32>| match pitch {
+            Some(hz) => format!("{:.1} Hz", hz),
+            None => String::from("-"),
+        };
+        egui::Grid::new("info_grid").show(ui, |ui| {
+            ui.label("Duration");
+            ui.label(format!("{:.3} s", features.duration));
+            ui.end_row();
+            ui.label("Attack");
+            ui.label(format!("{:.3} s", features.attack_time));
+            ui.end_row();
+            ui.label("Peak / RMS");
+            ui.label(format!("{:.3} / {:.3}", features.peak, features.rms));
+            ui.end_row();
+            ui.label("Spectral centroid");
+            ui.label(format!("{:.0} Hz", features.spectral_centroid));
+            ui.end_row();
+            ui.label("Pitch");
+            ui.label(format!(
+                "{} to {} ({:?})",
+                pitch(features.pitch_start),
+                pitch(features.pitch_end),
+                features.pitch_direction
+            ));
+            ui.end_row();
+            ui.label("Noisiness");
+            ui.label(format!("{:.2}", features.noisiness));
+            ui.end_row();
+            ui.label("Zero crossings");
+            ui.label(format!("{:.0} /s", features.zero_crossing_rate));
+            ui.end_row();
+        });
+    }
+
+    /// Settings of the cleanup pass, returns whether they changed.
+    fn cleanup(&mut self, ui: &mut egui::Ui) -> bool {
+        let options = &mut self.cleanup_options;
+        let mut changed = ui.checkbox(&mut options.dc_block, "Remove DC").changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut options.trim_threshold, 0.0..=0.01)
+                    .logarithmic(true)
+                    .text("Trim below"),
+            )
+            .changed();
+        for (value, label) in [
+            (&mut options.fade_in, "Fade in"),
+            (&mut options.fade_out, "Fade out"),
+        ] {
+            changed |= ui
+                .add(Slider::new(value, 0.0..=0.05).text(label).suffix(" s"))
+                .changed();
+        }
+        changed |= ui
+            .add(
+                Slider::new(&mut options.repeat_fade, 0.0..=0.05)
+                    .text("Repeat fade")
+                    .suffix(" s"),
+            )
+            .on_hover_text("Single sounds only, layers don't fade their repeats")
+            .changed();
+        changed
+    }
+
+    fn export(&mut self, ui: &mut egui::Ui) {
+        ui.label("Output");
+        if ui
+            .checkbox(&mut self.live, "Live")
+            .on_hover_text("Play on the live synth, pitch, vibrato and cutoffs change as it plays")
+            .changed()
+        {
+            self.play();
+        }
+        if self.live {
+            ui.horizontal(|ui| {
+                if ui.checkbox(&mut self.params.env_hold, "Hold").changed() {
+                    self.play();
+                }
+                if self.params.env_hold && ui.button("Release").clicked() {
+                    self.send_live(SynthCommand::Release);
+                }
+            });
+        }
+        if ui.checkbox(&mut self.clean, "Clean up").changed() {
+            self.play();
+        }
+        if self.clean && self.cleanup(ui) {
+            self.play();
+        }
+        if ui.checkbox(&mut self.looping, "Loop").changed() {
+            self.play();
+        }
+        if self.looping {
+            let mut changed = ui.checkbox(&mut self.custom_loop, "Set points").changed();
+            if self.custom_loop {
+                ui.horizontal(|ui| {
+                    for (value, label) in self.loop_points.iter_mut().zip(["Start", "End"]) {
+                        ui.label(label);
+                        changed |= ui
+                            .add(
+                                egui::DragValue::new(value)
+                                    .range(0.0..=10.0)
+                                    .speed(0.001)
+                                    .suffix(" s"),
+                            )
+                            .changed();
+                    }
+                });
+            }
+            if changed {
+                self.play();
+            }
+        }
+        if self.looping && self.loop_region.is_none() {
+            ui.label(if self.custom_loop {
+                "Empty loop"
+            } else {
+                "No loop found"
+            });
+        }
+        ui.checkbox(&mut self.convolve, "Impulse response");
+        if self.convolve {
+            ui.add_sized([100.0, 20.0], egui::TextEdit::singleline(&mut self.ir_path));
+            ui.add(Slider::new(&mut self.convolution.mix, 0.0..=1.0).text("Wet/Dry"));
+            ui.add(
+                Slider::new(&mut self.convolution.pre_delay, 0.0..=0.5)
+                    .text("Pre-delay")
+                    .suffix(" s"),
+            );
+        }
+        ui.add_sized(
+            [100.0, 20.0],
+            egui::TextEdit::singleline(&mut self.export_path),
+        );
+        if ui
+            .add_sized([100.0, 30.0], egui::Button::new("Export WAV"))
+            .clicked()
+        {
+            self.export_wav();
+        }
+
+        ui.add_sized(
+            [100.0, 20.0],
+            egui::TextEdit::singleline(&mut self.code_path),
+        );
+        egui::ComboBox::from_id_salt("code_format")
+            .selected_text(self.code_options.format.name())
+            .show_ui(ui, |ui| {
+                for format in SampleFormat::ALL {
+                    ui.selectable_value(&mut self.code_options.format, format, format.name());
+                }
+            });
+        ui.add(
+            Slider::new(&mut self.code_options.sample_rate, MIN_RATE..=SAMPLE_RATE)
+                .text("Rate")
+                .suffix(" Hz"),
+        );
+        ui.checkbox(&mut self.code_params, "Params only (Rust)");
+        if ui
+            .add_sized([100.0, 30.0], egui::Button::new("Export as code"))
+            .clicked()
+        {
+            self.export_code();
+        }
+    }
+
+    /// Writes the last rendered sound, or its params, as source code to
+    /// the code path. A playing layered sound is exported with its
+    /// layers, and has no const params.
+    fn export_code(&self) {
+        if let Err(e) = self.write_code() {
+            eprintln!("Failed to export {}: {}", self.code_path, e);
+        }
+    }
+
+    fn write_code(&self) -> anyhow::Result<()> {
+        let form = match CodeForm::from_path(&self.code_path) {
+            _ if self.code_params => CodeForm::RustParams,
+            Some(form) => form,
+            None => anyhow::bail!("Unknown code file, use .h or .rs"),
+        };
+        let sound = if self.convolve {
+            let ir = ImpulseResponse::load(&self.ir_path)?;
+            convolve(&self.sound, &ir, &self.convolution)
+        } else {
+            self.sound.clone()
+        };
+        let source = match self.layered_sound {
+            Some(_) => Source::Layers(&self.layered),
+            None => Source::Params(&self.params),
+        };
+        code::save(&self.code_path, form, &sound, source, &self.code_options)
+    }
+
+    /// Writes the last rendered sound to the export path, convolved
+    /// with the impulse response when enabled.
+    fn export_wav(&self) {
+        if let Err(e) = self.write_export() {
+            eprintln!("Failed to export {}: {}", self.export_path, e);
+        }
+    }
+
+    fn write_export(&self) -> anyhow::Result<()> {
+        let ir = if self.convolve {
+            Some(ImpulseResponse::load(&self.ir_path)?)
+        } else {
+            None
+        };
+
+        match (&self.layered_sound, ir) {
+            (Some(frames), Some(ir)) => {
+                let frames = convolve_stereo(frames, &ir, &self.convolution);
+                wav::save_stereo(&self.export_path, &frames, SAMPLE_RATE)
+            }
+            (Some(frames), None) => wav::save_stereo(&self.export_path, frames, SAMPLE_RATE),
+            (None, Some(ir)) => {
+                let sound = convolve(&self.sound, &ir, &self.convolution);
+                // the reverb changes the waveform, a loop found on the dry
+                // sound would click, set points are kept
+                let loop_region = if self.custom_loop {
+                    self.loop_region
+                } else {
+                    self.loop_region
+                        .and_then(|_| find_loop(&sound, self.sustain.clone()))
+                };
+                wav::save_looped(&self.export_path, &sound, SAMPLE_RATE, loop_region)
+            }
+            (None, None) => wav::save_looped(
+                &self.export_path,
+                &self.sound,
+                SAMPLE_RATE,
+                self.loop_region,
+            ),
+        }
+    }
+
+    fn layers(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut selected = None;
+        let mut removed = None;
+        for (i, layer) in self.layered.layers.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui
+                    .selectable_label(i == self.selected_layer, format!("Track {}", i + 1))
+                    .clicked()
+                {
+                    selected = Some(i);
+                }
+                ui.label(format!("{:?}", layer.params.wave_type));
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut layer.delay)
+                            .range(0.0..=2.0)
+                            .speed(0.005),
+                    )
+                    .on_hover_text("Delay in seconds")
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut layer.gain)
+                            .range(0.0..=2.0)
+                            .speed(0.01),
+                    )
+                    .on_hover_text("Gain")
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut layer.pitch)
+                            .range(-24.0..=24.0)
+                            .speed(0.1),
+                    )
+                    .on_hover_text("Pitch in semitones")
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut layer.pan)
+                            .range(-1.0..=1.0)
+                            .speed(0.01),
+                    )
+                    .on_hover_text("Pan")
+                    .changed();
+            });
+        }
+
+        // the sliders edit the selected track
+        if let Some(i) = selected {
+            self.select_layer(i);
+            changed = true;
+        }
+        if let Some(i) = removed {
+            self.layered.layers.remove(i);
+            self.select_layer(
+                self.selected_layer
+                    .min(self.layered.layers.len().max(1) - 1),
+            );
+            changed = true;
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add track").clicked() {
+                self.layered.layers.push(Layer {
+                    sample: self.synth.sample().cloned(),
+                    cycle: self.synth.cycle().cloned(),
+                    ..Layer::new(self.params)
+                });
+                self.selected_layer = self.layered.layers.len() - 1;
+                changed = true;
+            }
+            ui.text_edit_singleline(&mut self.layers_path);
+            if ui.button("Save").clicked() {
+                self.save_layers();
+            }
+            if ui.button("Load").clicked() {
+                self.load_layers();
+                changed = true;
+            }
+        });
+
+        if changed {
+            self.play();
+        }
+    }
+
+    /// Makes the sliders edit track `i`, with its sample and cycle.
+    fn select_layer(&mut self, i: usize) {
+        self.selected_layer = i;
+        if let Some(layer) = self.layered.layers.get(i) {
+            self.params = layer.params;
+            self.synth.set_sample(layer.sample.clone());
+            self.synth.set_cycle(layer.cycle.clone());
+        }
+    }
+
+    fn save_layers(&self) {
+        if let Err(e) = self.layered.save(&self.layers_path) {
+            eprintln!("Failed to save {}: {}", self.layers_path, e);
+        }
+    }
+
+    fn load_layers(&mut self) {
+        match LayeredSound::load(&self.layers_path) {
+            Ok(layered) => {
+                self.layered = layered;
+                self.select_layer(0);
+            }
+            Err(e) => eprintln!("Failed to load {}: {}", self.layers_path, e),
+        }
+    }
+
+    fn waveform(&mut self, ui: &mut egui::Ui) {
+        ui.label("Waveform");
+        let mut changed = false;
+        let mut drawing = false;
+        egui::ComboBox::from_id_salt("wave_type")
+            .selected_text(format!("{:?}", self.params.wave_type))
+            .show_ui(ui, |ui| {
+                for wave_type in WaveType::ALL {
+                    changed |= ui
+                        .selectable_value(
+                            &mut self.params.wave_type,
+                            wave_type,
+                            format!("{:?}", wave_type),
+                        )
+                        .changed();
+                }
+            });
+
+        if self.params.wave_type == WaveType::Wavetable {
+            let wavetable = self.params.wavetable.get_or_insert_with(Wavetable::default);
+            match self.wavetable_editor.show(ui, wavetable) {
+                Edit::Drawing => drawing = true,
+                Edit::Finished => changed = true,
+                Edit::None => {}
+            }
+
+            let mut len = wavetable.points().len();
+            if ui
+                .add(
+                    Slider::new(&mut len, MIN_POINTS..=MAX_POINTS)
+                        .logarithmic(true)
+                        .text("Points"),
+                )
+                .changed()
+            {
+                wavetable.resize(len);
+                changed = true;
+            }
+            if ui.button("Reset to sine").clicked() {
+                *wavetable = Wavetable::sine(len);
+                changed = true;
+            }
+
+            ui.horizontal(|ui| {
+                ui.add_sized(
+                    [100.0, 20.0],
+                    egui::TextEdit::singleline(&mut self.wavetable_path),
+                );
+                if ui.button("Import WAV").clicked() {
+                    match Cycle::load(&self.wavetable_path) {
+                        Ok(cycle) => {
+                            *wavetable = cycle.wavetable();
+                            let detailed = cycle.is_detailed().then(|| Arc::new(cycle));
+                            self.synth.set_cycle(detailed);
+                            changed = true;
+                        }
+                        Err(e) => eprintln!("Failed to load {}: {}", self.wavetable_path, e),
+                    }
+                }
+            });
+            // the imported cycle plays until the table is edited
+            if let Some(cycle) = self.synth.cycle()
+                && cycle.wavetable() == *wavetable
+            {
+                ui.label(format!("{} imported points", cycle.points().len()));
+            }
+        }
+
+        if self.params.wave_type == WaveType::Sample {
+            changed |= self.sample_source(ui);
+        }
+
+        if self.params.wave_type == WaveType::Fm {
+            let fm = &mut self.params.fm;
+            changed |= slider(ui, "Ratio", &mut fm.ratio, 0.0, MAX_RATIO).changed();
+            changed |= slider(ui, "Index", &mut fm.index, 0.0, MAX_INDEX).changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut fm.index_sweep, -MAX_INDEX..=MAX_INDEX)
+                        .text("Index Sweep")
+                        .suffix(" /s"),
+                )
+                .changed();
+        }
+
+        let mut ring = self.params.ring_mod.is_some();
+        if ui.checkbox(&mut ring, "Ring modulation").changed() {
+            self.params.ring_mod = ring.then(RingModParams::default);
+            changed = true;
+        }
+        if let Some(ring_mod) = &mut self.params.ring_mod {
+            changed |= slider(ui, "Ring Ratio", &mut ring_mod.ratio, 0.0, MAX_RATIO).changed();
+            changed |= slider(ui, "Ring Mix", &mut ring_mod.mix, 0.0, 1.0).changed();
+        }
+
+        ui.horizontal(|ui| {
+            let name = |chip: Option<Chip>| chip.map_or("Any", Chip::name);
+            egui::ComboBox::from_label("Hardware")
+                .selected_text(name(self.params.chip))
+                .show_ui(ui, |ui| {
+                    for chip in [None].into_iter().chain(Chip::ALL.map(Some)) {
+                        changed |= ui
+                            .selectable_value(&mut self.params.chip, chip, name(chip))
+                            .changed();
+                    }
+                });
+            if let Some(chip) = self.params.chip
+                && ui.button("Snap to hardware").clicked()
+            {
+                self.params = chip.snap(&self.params);
+                changed = true;
+            }
+        });
+
+        if changed {
+            self.play();
+        } else if drawing {
+            self.preview();
+        }
+    }
+
+    /// Controls of the sample wave type, returns whether they changed.
+    fn sample_source(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.add_sized(
+                [100.0, 20.0],
+                egui::TextEdit::singleline(&mut self.sample_path),
+            );
+            if ui.button("Load WAV").clicked() {
+                match Sample::load(&self.sample_path) {
+                    Ok(sample) => {
+                        self.synth.set_sample(Some(Arc::new(sample)));
+                        changed = true;
+                    }
+                    Err(e) => eprintln!("Failed to load {}: {}", self.sample_path, e),
+                }
+            }
+        });
+        match self.synth.sample() {
+            Some(sample) => ui.label(format!("{:.2} s loaded", sample.duration())),
+            None => ui.label("No sample loaded"),
+        };
+
+        let start_pitch = self.params.pitch_hz(SAMPLE_RATE);
+        let sample = &mut self.params.sample;
+        ui.horizontal(|ui| {
+            for mode in SampleMode::ALL {
+                changed |= ui
+                    .radio_value(&mut sample.mode, mode, format!("{:?}", mode))
+                    .changed();
+            }
+        });
+        changed |= ui
+            .add(
+                Slider::new(&mut sample.root, 20.0..=5000.0)
+                    .logarithmic(true)
+                    .text("Root")
+                    .suffix(" Hz"),
+            )
+            .changed();
+        if ui.button("Recorded speed at start pitch").clicked() {
+            sample.root = start_pitch;
+            changed = true;
+        }
+        changed
+    }
+
     fn envelope(&mut self, ui: &mut egui::Ui) {
         ui.label("Envelope");
         if slider(ui, "Attack time", &mut self.params.env_attack, 0.0, 1.0).changed() {
@@ -115,11 +962,55 @@ impl RefexerApp {
         }
     }
 
+    fn custom_envelopes(&mut self, ui: &mut egui::Ui) {
+        let classic = Envelope::from_classic(&self.params);
+        let envelopes = [
+            ("volume", &mut self.params.volume_env, classic, 0.0..=3.0),
+            (
+                "pitch",
+                &mut self.params.pitch_env,
+                Envelope::flat(0.0),
+                -1.0..=1.0,
+            ),
+            (
+                "filter",
+                &mut self.params.filter_env,
+                Envelope::flat(0.0),
+                -1.0..=1.0,
+            ),
+        ];
+
+        let mut changed = false;
+        for ((name, envelope, initial, levels), editor) in
+            envelopes.into_iter().zip(&mut self.envelope_editors)
+        {
+            let mut enabled = envelope.is_some();
+            if ui
+                .checkbox(&mut enabled, format!("Custom {} envelope", name))
+                .changed()
+            {
+                *envelope = enabled.then_some(initial);
+                changed = true;
+            }
+            if let Some(envelope) = envelope {
+                changed |= editor.show(ui, envelope, levels);
+            }
+        }
+
+        if changed {
+            self.play();
+        }
+    }
+
     fn frequency(&mut self, ui: &mut egui::Ui) {
         ui.label("Frequency");
         if slider(ui, "Start frequency", &mut self.params.base_freq, 0.0, 1.0).changed() {
-            self.play();
+            if self.snap_pitch {
+                self.snap_to_scale();
+            }
+            self.live_changed(LiveParam::BaseFreq);
         }
+        self.pitch(ui);
         if slider(ui, "Min frequency", &mut self.params.freq_limit, 0.0, 1.0).changed() {
             self.play();
         }
@@ -131,23 +1022,109 @@ impl RefexerApp {
         }
     }
 
+    /// Shows the starting pitch and the scale it snaps to.
+    fn pitch(&mut self, ui: &mut egui::Ui) {
+        let hz = self.params.pitch_hz(SAMPLE_RATE);
+        let note = Note::from_hz(hz);
+        ui.label(format!(
+            "{:.1} Hz, {} {:+.0} cents",
+            hz,
+            note.name(),
+            note.cents
+        ));
+
+        let mut changed = ui.checkbox(&mut self.snap_pitch, "Snap to scale").changed();
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("scale_root")
+                .width(50.0)
+                .selected_text(NOTE_NAMES[self.scale_root as usize])
+                .show_ui(ui, |ui| {
+                    for (root, name) in NOTE_NAMES.iter().enumerate() {
+                        changed |= ui
+                            .selectable_value(&mut self.scale_root, root as i32, *name)
+                            .changed();
+                    }
+                });
+            egui::ComboBox::from_id_salt("scale")
+                .selected_text(self.scale.name())
+                .show_ui(ui, |ui| {
+                    for scale in Scale::ALL {
+                        changed |= ui
+                            .selectable_value(&mut self.scale, scale, scale.name())
+                            .changed();
+                    }
+                });
+        });
+
+        if changed && self.snap_pitch {
+            self.snap_to_scale();
+            self.live_changed(LiveParam::BaseFreq);
+        }
+    }
+
+    /// Moves the start frequency to the nearest note of the scale.
+    fn snap_to_scale(&mut self) {
+        let note = self.params.note(SAMPLE_RATE);
+        let midi = note.midi as f32 + note.cents / 100.0;
+        let snapped = self.scale.snap(self.scale_root, midi);
+        self.params = self.params.with_note(Note::new(snapped, 0.0), SAMPLE_RATE);
+    }
+
     fn vibrato(&mut self, ui: &mut egui::Ui) {
         ui.label("Vibrato");
         if slider(ui, "Depth", &mut self.params.vib_strength, 0.0, 1.0).changed() {
-            self.play();
+            self.live_changed(LiveParam::VibStrength);
         }
         if slider(ui, "Speed", &mut self.params.vib_speed, 0.0, 1.0).changed() {
-            self.play();
+            self.live_changed(LiveParam::VibSpeed);
         }
     }
 
     fn arpeggios(&mut self, ui: &mut egui::Ui) {
         ui.vertical(|ui| {
             ui.label("Change");
-            if slider(ui, "Amount", &mut self.params.arp_mod, -1.0, 1.0).changed() {
+            let mut pattern = self.params.arpeggio.is_some();
+            if ui.checkbox(&mut pattern, "Semitone pattern").changed() {
+                self.params.arpeggio = pattern.then(Arpeggio::default);
                 self.play();
             }
-            if slider(ui, "Speed", &mut self.params.arp_speed, 0.0, 1.0).changed() {
+
+            let Some(arpeggio) = &mut self.params.arpeggio else {
+                if slider(ui, "Amount", &mut self.params.arp_mod, -1.0, 1.0).changed() {
+                    self.play();
+                }
+                if slider(ui, "Speed", &mut self.params.arp_speed, 0.0, 1.0).changed() {
+                    self.play();
+                }
+                return;
+            };
+
+            let mut changed = false;
+            let response = ui.text_edit_singleline(&mut self.arp_pattern);
+            if response.changed() {
+                let steps: Vec<f32> = self
+                    .arp_pattern
+                    .split([' ', ','])
+                    .filter_map(|step| step.trim().parse().ok())
+                    .collect();
+                arpeggio.set_steps(&steps);
+                changed = true;
+            }
+            if !response.has_focus() {
+                let steps: Vec<String> = arpeggio.steps().iter().map(f32::to_string).collect();
+                self.arp_pattern = steps.join(" ");
+            }
+            changed |= ui
+                .add(
+                    Slider::new(&mut arpeggio.step_time, 0.01..=0.5)
+                        .logarithmic(true)
+                        .suffix(" s")
+                        .text("Step time"),
+                )
+                .changed();
+            changed |= ui.checkbox(&mut arpeggio.looping, "Loop").changed();
+
+            if changed {
                 self.play();
             }
         });
@@ -180,10 +1157,77 @@ impl RefexerApp {
         }
     }
 
+    fn lfos(&mut self, ui: &mut egui::Ui) {
+        for i in 0..self.params.lfos.len() {
+            ui.label(format!("LFO {}", i + 1));
+
+            let mut changed = false;
+            let lfo = &mut self.params.lfos[i];
+            egui::ComboBox::from_id_salt(("lfo_target", i))
+                .selected_text(format!("{:?}", lfo.target))
+                .show_ui(ui, |ui| {
+                    for target in LfoTarget::ALL {
+                        changed |= ui
+                            .selectable_value(&mut lfo.target, target, format!("{:?}", target))
+                            .changed();
+                    }
+                });
+            egui::ComboBox::from_id_salt(("lfo_shape", i))
+                .selected_text(format!("{:?}", lfo.shape))
+                .show_ui(ui, |ui| {
+                    for shape in LfoShape::ALL {
+                        changed |= ui
+                            .selectable_value(&mut lfo.shape, shape, format!("{:?}", shape))
+                            .changed();
+                    }
+                });
+            changed |= slider(ui, "Rate", &mut lfo.rate, 0.0, 1.0).changed();
+            changed |= slider(ui, "Depth", &mut lfo.depth, 0.0, 1.0).changed();
+
+            if changed {
+                self.play();
+            }
+            ui.add_space(12.0);
+        }
+    }
+
+    fn effects(&mut self, ui: &mut egui::Ui) {
+        let effects = &mut self.params.effects;
+        let mut changed = false;
+
+        ui.label("Distortion");
+        changed |= slider(ui, "Drive", &mut effects.distortion.drive, 0.0, 1.0).changed();
+        changed |= slider(ui, "Wet/Dry", &mut effects.distortion.mix, 0.0, 1.0).changed();
+        ui.add_space(12.0);
+
+        ui.label("Chorus / Flanger");
+        changed |= slider(ui, "Rate", &mut effects.chorus.rate, 0.0, 1.0).changed();
+        changed |= slider(ui, "Depth", &mut effects.chorus.depth, 0.0, 1.0).changed();
+        changed |= slider(ui, "Delay", &mut effects.chorus.delay, 0.0, 1.0).changed();
+        changed |= slider(ui, "Feedback", &mut effects.chorus.feedback, 0.0, 1.0).changed();
+        changed |= slider(ui, "Wet/Dry", &mut effects.chorus.mix, 0.0, 1.0).changed();
+        ui.add_space(12.0);
+
+        ui.label("Echo");
+        changed |= slider(ui, "Time", &mut effects.delay.time, 0.0, 1.0).changed();
+        changed |= slider(ui, "Feedback", &mut effects.delay.feedback, 0.0, 1.0).changed();
+        changed |= slider(ui, "Wet/Dry", &mut effects.delay.mix, 0.0, 1.0).changed();
+        ui.add_space(12.0);
+
+        ui.label("Reverb");
+        changed |= slider(ui, "Room Size", &mut effects.reverb.room_size, 0.0, 1.0).changed();
+        changed |= slider(ui, "Damping", &mut effects.reverb.damping, 0.0, 1.0).changed();
+        changed |= slider(ui, "Wet/Dry", &mut effects.reverb.mix, 0.0, 1.0).changed();
+
+        if changed {
+            self.play();
+        }
+    }
+
     fn low_pass(&mut self, ui: &mut egui::Ui) {
         ui.label("Low-Pass Filter");
         if slider(ui, "Cutoff", &mut self.params.lpf_freq, 0.0, 1.0).changed() {
-            self.play();
+            self.live_changed(LiveParam::LpfFreq);
         }
         if slider(ui, "Cutoff Sweep", &mut self.params.lpf_ramp, -1.0, 1.0).changed() {
             self.play();
@@ -196,12 +1240,53 @@ impl RefexerApp {
     fn high_pass(&mut self, ui: &mut egui::Ui) {
         ui.label("High-Pass Filter");
         if slider(ui, "Cutoff", &mut self.params.hpf_freq, 0.0, 1.0).changed() {
-            self.play();
+            self.live_changed(LiveParam::HpfFreq);
         }
         if slider(ui, "Cutoff Sweep", &mut self.params.hpf_ramp, -1.0, 1.0).changed() {
             self.play();
         }
     }
+
+    fn state_variable_filter(&mut self, ui: &mut egui::Ui) {
+        ui.label("State-Variable Filter");
+        let mut enabled = self.params.filter.is_some();
+        let mut changed = ui.checkbox(&mut enabled, "Replace LPF / HPF").changed();
+        if changed {
+            self.params.filter = enabled.then(FilterParams::default);
+        }
+
+        if let Some(filter) = &mut self.params.filter {
+            egui::ComboBox::from_id_salt("filter_mode")
+                .selected_text(format!("{:?}", filter.mode))
+                .show_ui(ui, |ui| {
+                    for mode in FilterMode::ALL {
+                        changed |= ui
+                            .selectable_value(&mut filter.mode, mode, format!("{:?}", mode))
+                            .changed();
+                    }
+                });
+            changed |= ui
+                .add(
+                    Slider::new(&mut filter.cutoff, MIN_CUTOFF..=MAX_CUTOFF)
+                        .logarithmic(true)
+                        .text("Cutoff")
+                        .suffix(" Hz"),
+                )
+                .changed();
+            changed |= slider(ui, "Resonance", &mut filter.resonance, 0.0, 1.0).changed();
+            changed |= ui
+                .add(
+                    Slider::new(&mut filter.sweep, -8.0..=8.0)
+                        .text("Cutoff Sweep")
+                        .suffix(" oct/s"),
+                )
+                .changed();
+        }
+
+        if changed {
+            self.play();
+        }
+    }
 }
 
 fn slider(ui: &mut egui::Ui, label: &str, value: &mut f32, min: f32, max: f32) -> Response {
@@ -231,13 +1316,23 @@ impl eframe::App for RefexerApp {
                                 self.mutate_sound();
                             }
                             self.sound_button(ui, "Randomize", SoundType::Randomize);
+                            ui.add_space(48.0);
+
+                            self.morph(ui);
+                            ui.add_space(24.0);
+
+                            self.export(ui);
                         });
                         ui.allocate_ui(vec2(200.0, ui.available_size_before_wrap().y), |ui| {
                             ui.with_layout(
                                 Layout::top_down(egui::Align::Min).with_main_wrap(true),
                                 |ui| {
+                                    self.waveform(ui);
+                                    ui.add_space(24.0);
                                     self.envelope(ui);
                                     ui.add_space(24.0);
+                                    self.custom_envelopes(ui);
+                                    ui.add_space(24.0);
                                     self.frequency(ui);
                                     ui.add_space(24.0);
                                     self.vibrato(ui);
@@ -254,11 +1349,20 @@ impl eframe::App for RefexerApp {
                                     ui.add_space(24.0);
                                     self.high_pass(ui);
                                     ui.add_space(24.0);
+                                    self.state_variable_filter(ui);
+                                    ui.add_space(24.0);
+                                    self.lfos(ui);
+                                    ui.add_space(24.0);
+                                    self.effects(ui);
+                                    ui.add_space(24.0);
                                 },
                             )
                         });
                     });
                     // ui.set_min_height(200.0);
+                    egui::CollapsingHeader::new("Layers").show(ui, |ui| self.layers(ui));
+                    egui::CollapsingHeader::new("Info").show(ui, |ui| self.info(ui));
+                    egui::CollapsingHeader::new("Evolve").show(ui, |ui| self.evolve(ui));
                     self.waveform_plot.show_plot(ui);
                 })
             });
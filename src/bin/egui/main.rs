@@ -3,17 +3,22 @@ use cpal::traits::StreamTrait;
 use std::sync::mpsc;
 
 use eframe::egui;
-use refexer::sound::stream_setup;
+use refexer::sound::{live_stream_setup, stereo_stream_setup};
 
+mod envelope;
 mod gui;
 mod plot;
+mod wavetable;
 
 fn main() -> anyhow::Result<()> {
     let (tx, rx) = mpsc::channel();
+    let (live_tx, live_rx) = mpsc::channel();
 
-    // initialize the synth and the audio stream
-    let (stream, _) = stream_setup(rx)?;
+    // initialize the synth and the audio streams, rendered and live
+    let (stream, _) = stereo_stream_setup(rx)?;
     stream.play()?;
+    let (live_stream, _) = live_stream_setup(live_rx)?;
+    live_stream.play()?;
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([640.0, 700.0]),
@@ -23,7 +28,7 @@ fn main() -> anyhow::Result<()> {
     eframe::run_native(
         "Refexer - Retro Sound FX Generator",
         options,
-        Box::new(|_cc| Ok(Box::new(gui::RefexerApp::new(tx)))),
+        Box::new(|_cc| Ok(Box::new(gui::RefexerApp::new(tx, live_tx)))),
     )
     .map_err(|e| anyhow!("Failed to start eframe: {}", e))
 }
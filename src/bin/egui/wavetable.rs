@@ -0,0 +1,81 @@
+use eframe::egui::{self, Pos2, Sense, Stroke, StrokeKind, pos2, vec2};
+use refexer::synth::wavetable::Wavetable;
+
+/// What the editor did to the wavetable during a frame.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    None,
+    /// The waveform is being drawn.
+    Drawing,
+    /// The mouse was released after drawing.
+    Finished,
+}
+
+/// Canvas to draw a single-cycle waveform with the mouse.
+#[derive(Default)]
+pub struct WavetableEditor {
+    /// Position and level under the pointer on the previous frame,
+    /// joined to the current one so fast strokes leave no gaps
+    last: Option<(f32, f32)>,
+}
+
+impl WavetableEditor {
+    pub fn show(&mut self, ui: &mut egui::Ui, wavetable: &mut Wavetable) -> Edit {
+        let (response, painter) = ui.allocate_painter(vec2(200.0, 100.0), Sense::drag());
+        let rect = response.rect.shrink(4.0);
+
+        let len = wavetable.points().len();
+        let to_screen = |index: f32, level: f32| {
+            pos2(
+                rect.left() + index / len as f32 * rect.width(),
+                rect.center().y - level * rect.height() * 0.5,
+            )
+        };
+        let from_screen = |pos: Pos2| {
+            let index = (pos.x - rect.left()) / rect.width() * len as f32;
+            let level = (rect.center().y - pos.y) / (rect.height() * 0.5);
+            (index.clamp(0.0, len as f32 - 1.0), level.clamp(-1.0, 1.0))
+        };
+
+        let mut edit = Edit::None;
+        if response.dragged()
+            && let Some(pos) = response.interact_pointer_pos()
+        {
+            let (index, level) = from_screen(pos);
+            let (last_index, last_level) = self.last.unwrap_or((index, level));
+            let (start, end) = (last_index.min(index), last_index.max(index));
+            let (start, end) = (start.round() as usize, end.round() as usize);
+            for (i, point) in wavetable.points_mut()[start..=end].iter_mut().enumerate() {
+                let t = if index != last_index {
+                    ((start + i) as f32 - last_index) / (index - last_index)
+                } else {
+                    1.0
+                };
+                *point = last_level + (level - last_level) * t.clamp(0.0, 1.0);
+            }
+            self.last = Some((index, level));
+            edit = Edit::Drawing;
+        }
+        if response.drag_stopped() {
+            self.last = None;
+            edit = Edit::Finished;
+        }
+
+        // background, zero line and waveform
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+        painter.rect_stroke(rect, 2.0, visuals.window_stroke, StrokeKind::Outside);
+        painter.hline(rect.x_range(), rect.center().y, visuals.window_stroke);
+
+        let points = wavetable.points();
+        let curve: Vec<Pos2> = points
+            .iter()
+            .chain(points.first())
+            .enumerate()
+            .map(|(i, &level)| to_screen(i as f32, level))
+            .collect();
+        painter.line(curve, Stroke::new(1.5, visuals.text_color()));
+
+        edit
+    }
+}
@@ -0,0 +1,15 @@
+pub mod chip;
+pub mod code;
+pub mod wav;
+
+/// Turns a sound name into a label valid in assembly, C and Rust.
+pub(crate) fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
@@ -0,0 +1,302 @@
+//! Frame-by-frame register streams for homebrew sound drivers.
+//!
+//! The sound is rendered in the chip mode of `synth::chip` and its
+//! pitch, duty cycle and volume are read once per video frame. Each
+//! frame becomes the values a driver writes to the channel registers,
+//! emitted as a table for ca65, RGBDS or C.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use super::identifier;
+use crate::synth::chip::{Channel, Chip, GB_WAVE_POINTS};
+use crate::synth::params::SynthParams;
+use crate::synth::{SAMPLE_RATE, Synth, Voice};
+
+/// Frames per second at which the registers are written.
+pub const FRAME_RATE: u32 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `.byte` tables for the ca65 assembler of cc65.
+    Ca65,
+    /// `db` tables for the RGBDS assembler.
+    Rgbds,
+    /// A C array.
+    C,
+}
+
+impl TryFrom<&str> for Format {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "ca65" => Ok(Self::Ca65),
+            "rgbds" => Ok(Self::Rgbds),
+            "c" => Ok(Self::C),
+            _ => Err(format!("Unknown register format: {}", value)),
+        }
+    }
+}
+
+/// Register writes of a sound, one row per frame.
+#[derive(Clone, Debug)]
+pub struct RegisterStream {
+    pub chip: Chip,
+    pub channel: Channel,
+    /// Values of `registers()` on each frame. The last frame silences
+    /// the channel. On the NES pulse, a high timer byte of `$ff` leaves
+    /// $4003 unwritten.
+    pub frames: Vec<Vec<u8>>,
+    /// Wave RAM of the Game Boy wave channel, two samples per byte with
+    /// the first in the high nibble.
+    pub wave_ram: Option<[u8; GB_WAVE_POINTS / 2]>,
+    /// Features of the sound the chip lacks, left out of the stream.
+    pub unsupported: Vec<String>,
+}
+
+impl RegisterStream {
+    /// Renders the sound in the chip mode and samples it at
+    /// `FRAME_RATE`.
+    pub fn capture(params: &SynthParams, chip: Chip) -> Self {
+        let params = SynthParams {
+            chip: Some(chip),
+            ..*params
+        };
+        let channel = chip.channel(params.wave_type);
+        let frame_length = (SAMPLE_RATE / FRAME_RATE) as usize;
+
+        let mut synth = Synth::new(params);
+        let hold = synth.sustain_range().end;
+        synth.play_sample();
+
+        let mut frames = Vec::new();
+        let mut previous = Previous::default();
+        let mut position = 0;
+        while synth.synth_sample().is_some() {
+            if position % frame_length == 0 {
+                frames.push(frame(chip, channel, synth.voice(), &mut previous));
+            }
+            position += 1;
+            if position >= hold {
+                synth.release();
+            }
+        }
+        let silence = Voice {
+            volume: 0.0,
+            ..synth.voice()
+        };
+        frames.push(frame(chip, channel, silence, &mut previous));
+
+        let wave_ram = (channel == Channel::Wave).then(|| {
+            let level = |i: usize| {
+                let value = Chip::wave(&params, i as f32 / GB_WAVE_POINTS as f32);
+                ((value + 1.0) * 7.5).round() as u8
+            };
+            std::array::from_fn(|i| level(2 * i) << 4 | level(2 * i + 1))
+        });
+
+        RegisterStream {
+            chip,
+            channel,
+            frames,
+            wave_ram,
+            unsupported: chip.unsupported(&params),
+        }
+    }
+
+    /// Names of the registers written on each frame, in order. The
+    /// Game Boy pulse is given as channel 1, channel 2 takes the same
+    /// values at NR21 to NR24.
+    pub fn registers(&self) -> &'static [&'static str] {
+        match (self.chip, self.channel) {
+            (Chip::Nes, Channel::Pulse) => &["$4000", "$4002", "$4003"],
+            (Chip::Nes, Channel::Noise) => &["$400C", "$400E", "$400F"],
+            (Chip::Nes, _) => &["$4008", "$400A", "$400B"],
+            (Chip::GameBoy, Channel::Pulse) => &["NR11", "NR12", "NR13", "NR14"],
+            (Chip::GameBoy, Channel::Noise) => &["NR42", "NR43", "NR44"],
+            (Chip::GameBoy, _) => &["NR32", "NR33", "NR34"],
+        }
+    }
+
+    /// Source code of the tables, labelled with `name`.
+    pub fn write(&self, format: Format, name: &str) -> String {
+        let name = identifier(name);
+        let channel = match self.channel {
+            Channel::Pulse => "pulse",
+            Channel::Triangle => "triangle",
+            Channel::Wave => "wave",
+            Channel::Noise => "noise",
+        };
+        let mut comments = vec![
+            format!(
+                "{}: {} {}, {} frames at {} Hz",
+                name,
+                self.chip.name(),
+                channel,
+                self.frames.len(),
+                FRAME_RATE
+            ),
+            match (self.chip, self.channel) {
+                (Chip::Nes, Channel::Pulse) => format!(
+                    "Each frame writes $4000, $4002, and $4003 unless its value is ${:02x}: \
+                     writing $4003 restarts the duty cycle and envelope.",
+                    KEEP_HIGH
+                ),
+                _ => format!("Each frame writes {}.", self.registers().join(", ")),
+            },
+        ];
+        if self.wave_ram.is_some() {
+            comments.push(format!(
+                "Load {}_wave into the wave RAM with NR30 off, then turn NR30 on.",
+                name
+            ));
+        }
+        if !self.unsupported.is_empty() {
+            comments.push(format!(
+                "Left out, the {} lacks: {}.",
+                self.chip.name(),
+                self.unsupported.join(", ")
+            ));
+        }
+
+        let mut out = String::new();
+        match format {
+            Format::Ca65 | Format::Rgbds => {
+                let (byte, label) = match format {
+                    Format::Ca65 => (".byte", ":"),
+                    _ => ("db", "::"),
+                };
+                for comment in &comments {
+                    let _ = writeln!(out, "; {}", comment);
+                }
+                let _ = match format {
+                    Format::Ca65 => writeln!(out, "{}_frames = {}", name, self.frames.len()),
+                    _ => writeln!(
+                        out,
+                        "DEF {}_FRAMES EQU {}",
+                        name.to_uppercase(),
+                        self.frames.len()
+                    ),
+                };
+                let _ = writeln!(out, "\n{}{}", name, label);
+                for frame in &self.frames {
+                    let values: Vec<String> = frame.iter().map(|v| format!("${:02x}", v)).collect();
+                    let _ = writeln!(out, "    {} {}", byte, values.join(", "));
+                }
+                if let Some(wave_ram) = &self.wave_ram {
+                    let values: Vec<String> =
+                        wave_ram.iter().map(|v| format!("${:02x}", v)).collect();
+                    let _ = writeln!(out, "\n{}_wave{}", name, label);
+                    let _ = writeln!(out, "    {} {}", byte, values.join(", "));
+                }
+            }
+            Format::C => {
+                for comment in &comments {
+                    let _ = writeln!(out, "/* {} */", comment);
+                }
+                let count = format!("{}_FRAMES", name.to_uppercase());
+                let _ = writeln!(out, "#define {} {}\n", count, self.frames.len());
+                let _ = writeln!(
+                    out,
+                    "const unsigned char {}[{}][{}] = {{",
+                    name,
+                    count,
+                    self.registers().len()
+                );
+                for frame in &self.frames {
+                    let values: Vec<String> =
+                        frame.iter().map(|v| format!("0x{:02x}", v)).collect();
+                    let _ = writeln!(out, "    {{{}}},", values.join(", "));
+                }
+                let _ = writeln!(out, "}};");
+                if let Some(wave_ram) = &self.wave_ram {
+                    let values: Vec<String> =
+                        wave_ram.iter().map(|v| format!("0x{:02x}", v)).collect();
+                    let _ = writeln!(
+                        out,
+                        "\nconst unsigned char {}_wave[{}] = {{{}}};",
+                        name,
+                        wave_ram.len(),
+                        values.join(", ")
+                    );
+                }
+            }
+        }
+        out
+    }
+
+    /// Writes the tables to a file at `path`, see `write`.
+    pub fn save(&self, path: impl AsRef<Path>, format: Format, name: &str) -> anyhow::Result<()> {
+        fs::write(path, self.write(format, name))?;
+        Ok(())
+    }
+}
+
+/// Value of the NES pulse high timer byte on frames where $4003 keeps
+/// its previous value. The byte written is never above 7.
+const KEEP_HIGH: u8 = 0xff;
+
+/// Registers written on the previous frame.
+#[derive(Default)]
+struct Previous {
+    /// The Game Boy retriggers the channel to change the volume.
+    volume: Option<u8>,
+    /// Writing $4003 restarts the NES pulse sequencer and envelope.
+    high: Option<u8>,
+}
+
+/// Register values of one frame, `previous` holding the values of the
+/// frame before.
+fn frame(chip: Chip, channel: Channel, voice: Voice, previous: &mut Previous) -> Vec<u8> {
+    let level = Chip::quantize_volume(channel, voice.volume);
+    let volume_code = match channel {
+        Channel::Wave => match level {
+            1.0 => 1,
+            0.5 => 2,
+            0.25 => 3,
+            _ => 0,
+        },
+        _ => (level * 15.0).round() as u8,
+    };
+    let trigger = match chip {
+        Chip::Nes => false,
+        // retriggering restarts the wave RAM, so it only starts the sound
+        Chip::GameBoy if channel == Channel::Wave => previous.volume.is_none(),
+        Chip::GameBoy => previous.volume != Some(volume_code),
+    };
+    previous.volume = Some(volume_code);
+    let trigger = if trigger { 0x80 } else { 0x00 };
+
+    if channel == Channel::Noise {
+        // the synth noise changes 32 times per period
+        let noise = chip.noise_register(32.0 * voice.hz);
+        return match chip {
+            Chip::Nes => vec![0x30 | volume_code, noise, 0x00],
+            Chip::GameBoy => vec![volume_code << 4, noise, trigger],
+        };
+    }
+
+    let duty = match Chip::quantize_duty(voice.duty) {
+        0.125 => 0,
+        0.25 => 1,
+        _ => 2,
+    };
+    let timer = chip.timer(channel, voice.hz);
+    let [low, high] = timer.to_le_bytes();
+    match (chip, channel) {
+        (Chip::Nes, Channel::Triangle) => {
+            let control = if volume_code > 0 { 0xff } else { 0x80 };
+            vec![control, low, high]
+        }
+        (Chip::Nes, _) => {
+            let write = previous.high != Some(high);
+            previous.high = Some(high);
+            let high = if write { high } else { KEEP_HIGH };
+            vec![duty << 6 | 0x30 | volume_code, low, high]
+        }
+        (Chip::GameBoy, Channel::Wave) => vec![volume_code << 5, low, trigger | high],
+        (Chip::GameBoy, _) => vec![duty << 6, volume_code << 4, low, trigger | high],
+    }
+}
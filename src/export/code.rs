@@ -0,0 +1,592 @@
+//! Source code exports for embedding sounds in firmware and games.
+//!
+//! A rendered sound becomes a C header or a Rust static slice of
+//! samples, at the sample rate and bit depth of the target. A sound can
+//! also be exported as a `const SynthParams` literal, to be rendered at
+//! runtime by the crate. Every export starts with a comment holding the
+//! params, or the layers of a layered sound, as JSON, so the sound can
+//! be loaded again for editing.
+
+use std::f32::consts::{PI, TAU};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use super::identifier;
+use crate::analysis::resample;
+use crate::synth::SAMPLE_RATE;
+use crate::synth::envelope::Envelope;
+use crate::synth::layered::LayeredSound;
+use crate::synth::params::SynthParams;
+
+/// Samples written per line of the arrays.
+const VALUES_PER_LINE: usize = 16;
+/// Fraction of the target Nyquist frequency kept by the anti-aliasing
+/// filter, the rest is its transition band.
+const ANTI_ALIAS_PASSBAND: f32 = 0.85;
+/// Taps on each side of the anti-aliasing filter, per sample at the
+/// target rate.
+const ANTI_ALIAS_TAPS: usize = 16;
+/// Lowest sample rate of the exports, in Hz. Below it the anti-aliasing
+/// filter grows longer than most sounds.
+pub const MIN_RATE: u32 = 1000;
+
+/// What an exported sound was rendered from, written as JSON in its
+/// header comments.
+#[derive(Clone, Copy, Debug)]
+pub enum Source<'a> {
+    Params(&'a SynthParams),
+    Layers(&'a LayeredSound),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    I8,
+    /// 8-bit centered on 128, as read by most 8-bit DACs.
+    U8,
+    I16,
+}
+
+impl SampleFormat {
+    pub const ALL: [SampleFormat; 3] = [SampleFormat::I8, SampleFormat::U8, SampleFormat::I16];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleFormat::I8 => "8-bit signed",
+            SampleFormat::U8 => "8-bit unsigned",
+            SampleFormat::I16 => "16-bit signed",
+        }
+    }
+
+    fn c_type(self) -> &'static str {
+        match self {
+            SampleFormat::I8 => "int8_t",
+            SampleFormat::U8 => "uint8_t",
+            SampleFormat::I16 => "int16_t",
+        }
+    }
+
+    fn rust_type(self) -> &'static str {
+        match self {
+            SampleFormat::I8 => "i8",
+            SampleFormat::U8 => "u8",
+            SampleFormat::I16 => "i16",
+        }
+    }
+
+    fn quantize(self, sample: f32) -> i32 {
+        let sample = sample.clamp(-1.0, 1.0);
+        match self {
+            SampleFormat::I8 => (sample * 127.0).round() as i32,
+            SampleFormat::U8 => (sample * 127.0).round() as i32 + 128,
+            SampleFormat::I16 => (sample * 32767.0).round() as i32,
+        }
+    }
+}
+
+impl TryFrom<&str> for SampleFormat {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "i8" => Ok(Self::I8),
+            "8" => Ok(Self::I8),
+            "u8" => Ok(Self::U8),
+            "i16" => Ok(Self::I16),
+            "16" => Ok(Self::I16),
+            _ => Err(format!("Unknown sample format: {}", value)),
+        }
+    }
+}
+
+/// Shape of the exported code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeForm {
+    /// A C header with the samples in a `const` array.
+    CHeader,
+    /// Rust source with the samples in a `static` slice.
+    RustSamples,
+    /// Rust source with a `const SynthParams` literal.
+    RustParams,
+}
+
+impl CodeForm {
+    /// Form of the samples for a file extension: `.h` and `.c` give a
+    /// C header, `.rs` Rust source.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "h" | "c" => Some(CodeForm::CHeader),
+            "rs" => Some(CodeForm::RustSamples),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CodeOptions {
+    /// Sample rate of the exported samples, in Hz.
+    pub sample_rate: u32,
+    pub format: SampleFormat,
+}
+
+impl Default for CodeOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE,
+            format: SampleFormat::I16,
+        }
+    }
+}
+
+impl CodeOptions {
+    /// Sets the sample rate, rejecting rates outside of
+    /// `MIN_RATE..=SAMPLE_RATE`.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> anyhow::Result<()> {
+        if !(MIN_RATE..=SAMPLE_RATE).contains(&sample_rate) {
+            anyhow::bail!(
+                "Sample rate {} Hz out of range, use {} to {} Hz",
+                sample_rate,
+                MIN_RATE,
+                SAMPLE_RATE
+            );
+        }
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    /// Sample rate the samples are exported at, `sample_rate` clamped
+    /// into `MIN_RATE..=SAMPLE_RATE`.
+    pub fn rate(&self) -> u32 {
+        self.sample_rate.clamp(MIN_RATE, SAMPLE_RATE)
+    }
+}
+
+/// C header declaring the sound, rendered at `SAMPLE_RATE`, as a
+/// `const` array named `name`.
+pub fn c_header(name: &str, samples: &[f32], source: Source, options: &CodeOptions) -> String {
+    let name = identifier(name);
+    let upper = name.to_uppercase();
+    let values = quantize(samples, options);
+
+    let mut out = String::new();
+    for comment in comments(&name, values.len(), source, Some(options)) {
+        let _ = writeln!(out, "/* {} */", comment);
+    }
+    let _ = writeln!(out, "#ifndef {}_H", upper);
+    let _ = writeln!(out, "#define {}_H\n", upper);
+    let _ = writeln!(out, "#include <stdint.h>\n");
+    let _ = writeln!(out, "#define {}_SAMPLE_RATE {}", upper, options.rate());
+    let _ = writeln!(out, "#define {}_LENGTH {}\n", upper, values.len());
+    let _ = writeln!(
+        out,
+        "static const {} {}[{}_LENGTH] = {{",
+        options.format.c_type(),
+        name,
+        upper
+    );
+    write_values(&mut out, &values);
+    let _ = writeln!(out, "}};\n");
+    let _ = writeln!(out, "#endif");
+    out
+}
+
+/// Rust source declaring the sound, rendered at `SAMPLE_RATE`, as a
+/// `static` slice named after `name`.
+pub fn rust_samples(name: &str, samples: &[f32], source: Source, options: &CodeOptions) -> String {
+    let upper = identifier(name).to_uppercase();
+    let values = quantize(samples, options);
+
+    let mut out = String::new();
+    for comment in comments(&upper, values.len(), source, Some(options)) {
+        let _ = writeln!(out, "// {}", comment);
+    }
+    let _ = writeln!(
+        out,
+        "pub const {}_SAMPLE_RATE: u32 = {};\n",
+        upper,
+        options.rate()
+    );
+    let _ = writeln!(
+        out,
+        "pub static {}: &[{}] = &[",
+        upper,
+        options.format.rust_type()
+    );
+    write_values(&mut out, &values);
+    let _ = writeln!(out, "];");
+    out
+}
+
+/// Rust source declaring the params of the sound as a constant named
+/// after `name`, to be rendered with `Synth::new`.
+pub fn rust_params(name: &str, params: &SynthParams) -> String {
+    let upper = identifier(name).to_uppercase();
+
+    let mut out = String::new();
+    for comment in comments(&upper, 0, Source::Params(params), None) {
+        let _ = writeln!(out, "// {}", comment);
+    }
+    let _ = writeln!(out, "#[allow(unused_imports)]");
+    let _ = writeln!(out, "use refexer::synth::{{");
+    for path in [
+        "arpeggio::Arpeggio,",
+        "chip::Chip,",
+        "effects::{",
+        "    ChorusParams, DelayParams, DistortionParams, EffectParams, FilterMode, FilterParams,",
+        "    ReverbParams,",
+        "},",
+        "envelope::{Breakpoint, Envelope},",
+        "fm::{FmParams, RingModParams},",
+        "lfo::{Lfo, LfoShape, LfoTarget},",
+        "params::{SynthParams, WaveType},",
+        "sample::{SampleMode, SampleParams},",
+        "wavetable::Wavetable,",
+    ] {
+        let _ = writeln!(out, "    {}", path);
+    }
+    let _ = writeln!(out, "}};\n");
+    let _ = writeln!(
+        out,
+        "pub const {}: SynthParams = {};",
+        upper,
+        params_literal(params)
+    );
+    out
+}
+
+/// Writes the sound to `path` in the given form, named after the file.
+/// Layered sounds have no single params, so they only export samples.
+pub fn save(
+    path: impl AsRef<Path>,
+    form: CodeForm,
+    samples: &[f32],
+    source: Source,
+    options: &CodeOptions,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let name = path
+        .file_stem()
+        .map_or("sound".into(), |stem| stem.to_string_lossy());
+    let code = match form {
+        CodeForm::CHeader => c_header(&name, samples, source, options),
+        CodeForm::RustSamples => rust_samples(&name, samples, source, options),
+        CodeForm::RustParams => match source {
+            Source::Params(params) => rust_params(&name, params),
+            Source::Layers(_) => {
+                anyhow::bail!("Layered sounds have no const params, export their samples")
+            }
+        },
+    };
+    fs::write(path, code)?;
+    Ok(())
+}
+
+/// Header comments describing the export, its source last as JSON.
+fn comments(
+    name: &str,
+    length: usize,
+    source: Source,
+    options: Option<&CodeOptions>,
+) -> Vec<String> {
+    let mut comments = vec![match options {
+        Some(options) => format!(
+            "{}: {} samples, {}, {} Hz, generated by refexer",
+            name,
+            length,
+            options.format.name(),
+            options.rate()
+        ),
+        None => format!("{}: sound params, generated by refexer", name),
+    }];
+    let json = match source {
+        Source::Params(params) => serde_json::to_string(params).map(|json| ("params", json)),
+        Source::Layers(layers) => serde_json::to_string(layers).map(|json| ("layers", json)),
+    };
+    if let Ok((kind, json)) = json {
+        comments.push(format!("refexer {}: {}", kind, json));
+    }
+    comments
+}
+
+fn quantize(samples: &[f32], options: &CodeOptions) -> Vec<i32> {
+    let rate = options.rate();
+    resample(&anti_alias(samples, SAMPLE_RATE, rate), SAMPLE_RATE, rate)
+        .into_iter()
+        .map(|sample| options.format.quantize(sample))
+        .collect()
+}
+
+/// Low-passes `samples` below the Nyquist frequency of `to` with a
+/// Blackman windowed sinc, so that decimating them doesn't alias.
+fn anti_alias(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if to >= from {
+        return samples.to_vec();
+    }
+
+    let ratio = to as f32 / from as f32;
+    // cutoff in cycles per sample, a little under the new Nyquist
+    let cutoff = 0.5 * ratio * ANTI_ALIAS_PASSBAND;
+    let half = (ANTI_ALIAS_TAPS as f32 / ratio).ceil() as isize;
+    let taps: Vec<f32> = (-half..=half)
+        .map(|k| {
+            let x = k as f32;
+            let sinc = if k == 0 {
+                2.0 * cutoff
+            } else {
+                (TAU * cutoff * x).sin() / (PI * x)
+            };
+            // Blackman window over the 2 * half + 1 taps
+            let n = (x + half as f32) / half as f32;
+            sinc * (0.42 - 0.5 * (PI * n).cos() + 0.08 * (TAU * n).cos())
+        })
+        .collect();
+    let gain: f32 = taps.iter().sum();
+
+    (0..samples.len() as isize)
+        .map(|i| {
+            let sum: f32 = taps
+                .iter()
+                .zip(i - half..)
+                .filter_map(|(tap, j)| {
+                    usize::try_from(j)
+                        .ok()
+                        .and_then(|j| samples.get(j))
+                        .map(|s| tap * s)
+                })
+                .sum();
+            sum / gain
+        })
+        .collect()
+}
+
+fn write_values(out: &mut String, values: &[i32]) {
+    for line in values.chunks(VALUES_PER_LINE) {
+        let line: Vec<String> = line.iter().map(|value| value.to_string()).collect();
+        let _ = writeln!(out, "    {},", line.join(", "));
+    }
+}
+
+/// Rust expression of a float that reads back to the same value.
+fn float(value: f32) -> String {
+    if value.is_nan() {
+        "f32::NAN".to_string()
+    } else if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        format!("{}f32::INFINITY", sign)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn floats(values: &[f32]) -> String {
+    let values: Vec<String> = values.iter().map(|&value| float(value)).collect();
+    values.join(", ")
+}
+
+fn option<T>(value: Option<T>, literal: impl Fn(T) -> String) -> String {
+    match value {
+        Some(value) => format!("Some({})", literal(value)),
+        None => "None".to_string(),
+    }
+}
+
+fn envelope_literal(envelope: Envelope) -> String {
+    let points: Vec<String> = envelope
+        .points()
+        .iter()
+        .map(|point| {
+            format!(
+                "Breakpoint::new({}, {}, {})",
+                float(point.time),
+                float(point.level),
+                float(point.curve)
+            )
+        })
+        .collect();
+    format!("Envelope::from_sorted(&[{}])", points.join(", "))
+}
+
+/// Struct literal of the params, every field spelled out so it can be
+/// used in a constant.
+fn params_literal(params: &SynthParams) -> String {
+    let p = params;
+    let effects = &p.effects;
+    let fields = [
+        ("wave_type", format!("WaveType::{:?}", p.wave_type)),
+        (
+            "wavetable",
+            option(p.wavetable, |table| {
+                format!("Wavetable::from_points(&[{}])", floats(table.points()))
+            }),
+        ),
+        (
+            "sample",
+            format!(
+                "SampleParams {{ mode: SampleMode::{:?}, root: {} }}",
+                p.sample.mode,
+                float(p.sample.root)
+            ),
+        ),
+        (
+            "fm",
+            format!(
+                "FmParams {{ ratio: {}, index: {}, index_sweep: {} }}",
+                float(p.fm.ratio),
+                float(p.fm.index),
+                float(p.fm.index_sweep)
+            ),
+        ),
+        (
+            "ring_mod",
+            option(p.ring_mod, |ring_mod| {
+                format!(
+                    "RingModParams {{ ratio: {}, mix: {} }}",
+                    float(ring_mod.ratio),
+                    float(ring_mod.mix)
+                )
+            }),
+        ),
+        ("chip", option(p.chip, |chip| format!("Chip::{:?}", chip))),
+        ("base_freq", float(p.base_freq)),
+        ("freq_limit", float(p.freq_limit)),
+        ("freq_ramp", float(p.freq_ramp)),
+        ("freq_dramp", float(p.freq_dramp)),
+        ("duty", float(p.duty)),
+        ("duty_ramp", float(p.duty_ramp)),
+        ("vib_strength", float(p.vib_strength)),
+        ("vib_speed", float(p.vib_speed)),
+        ("env_attack", float(p.env_attack)),
+        ("env_sustain", float(p.env_sustain)),
+        ("env_decay", float(p.env_decay)),
+        ("env_punch", float(p.env_punch)),
+        ("env_hold", p.env_hold.to_string()),
+        ("volume_env", option(p.volume_env, envelope_literal)),
+        ("pitch_env", option(p.pitch_env, envelope_literal)),
+        ("filter_env", option(p.filter_env, envelope_literal)),
+        ("lpf_resonance", float(p.lpf_resonance)),
+        ("lpf_freq", float(p.lpf_freq)),
+        ("lpf_ramp", float(p.lpf_ramp)),
+        ("hpf_freq", float(p.hpf_freq)),
+        ("hpf_ramp", float(p.hpf_ramp)),
+        (
+            "filter",
+            option(p.filter, |filter| {
+                format!(
+                    "FilterParams {{ mode: FilterMode::{:?}, cutoff: {}, resonance: {}, sweep: {} }}",
+                    filter.mode,
+                    float(filter.cutoff),
+                    float(filter.resonance),
+                    float(filter.sweep)
+                )
+            }),
+        ),
+        ("pha_offset", float(p.pha_offset)),
+        ("pha_ramp", float(p.pha_ramp)),
+        ("repeat_speed", float(p.repeat_speed)),
+        ("arp_speed", float(p.arp_speed)),
+        ("arp_mod", float(p.arp_mod)),
+        (
+            "arpeggio",
+            option(p.arpeggio, |arpeggio| {
+                format!(
+                    "Arpeggio::new(&[{}], {}, {})",
+                    floats(arpeggio.steps()),
+                    float(arpeggio.step_time),
+                    arpeggio.looping
+                )
+            }),
+        ),
+        (
+            "lfos",
+            format!(
+                "[{}]",
+                p.lfos
+                    .iter()
+                    .map(|lfo| format!(
+                        "Lfo {{ shape: LfoShape::{:?}, target: LfoTarget::{:?}, rate: {}, depth: {} }}",
+                        lfo.shape,
+                        lfo.target,
+                        float(lfo.rate),
+                        float(lfo.depth)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ),
+        (
+            "effects",
+            format!(
+                "EffectParams {{ \
+                 distortion: DistortionParams {{ drive: {}, mix: {} }}, \
+                 chorus: ChorusParams {{ rate: {}, depth: {}, delay: {}, feedback: {}, mix: {} }}, \
+                 delay: DelayParams {{ time: {}, feedback: {}, mix: {} }}, \
+                 reverb: ReverbParams {{ room_size: {}, damping: {}, mix: {} }} }}",
+                float(effects.distortion.drive),
+                float(effects.distortion.mix),
+                float(effects.chorus.rate),
+                float(effects.chorus.depth),
+                float(effects.chorus.delay),
+                float(effects.chorus.feedback),
+                float(effects.chorus.mix),
+                float(effects.delay.time),
+                float(effects.delay.feedback),
+                float(effects.delay.mix),
+                float(effects.reverb.room_size),
+                float(effects.reverb.damping),
+                float(effects.reverb.mix)
+            ),
+        ),
+    ];
+
+    let mut literal = String::from("SynthParams {\n");
+    for (field, value) in fields {
+        let _ = writeln!(literal, "    {}: {},", field, value);
+    }
+    literal.push('}');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RMS of a sine at `hz` exported at 8 kHz, as a fraction of full
+    /// scale, away from the edges.
+    fn exported_rms(hz: f32) -> f32 {
+        let tone: Vec<f32> = (0..SAMPLE_RATE / 2)
+            .map(|i| 0.5 * (TAU * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect();
+        let options = CodeOptions {
+            sample_rate: 8000,
+            format: SampleFormat::I16,
+        };
+        let values = quantize(&tone, &options);
+        let middle = &values[500..values.len() - 500];
+        let power: f32 = middle
+            .iter()
+            .map(|&value| (value as f32 / i16::MAX as f32).powi(2))
+            .sum();
+        (power / middle.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn tones_above_the_target_nyquist_are_filtered() {
+        let kept = exported_rms(1000.0);
+        assert!((kept - 0.5 / 2f32.sqrt()).abs() < 0.01, "{kept}");
+        let aliased = exported_rms(6000.0);
+        assert!(aliased < kept * 0.001, "{aliased}");
+    }
+
+    #[test]
+    fn sample_rates_stay_in_range() {
+        let mut options = CodeOptions::default();
+        assert!(options.set_sample_rate(0).is_err());
+        assert!(options.set_sample_rate(SAMPLE_RATE + 1).is_err());
+        options.set_sample_rate(8000).unwrap();
+        assert_eq!(options.rate(), 8000);
+
+        options.sample_rate = 10;
+        assert_eq!(options.rate(), MIN_RATE);
+    }
+}
@@ -0,0 +1,147 @@
+//! Minimal WAV writer for rendered sounds.
+//!
+//! Sounds are written as mono or stereo 16-bit PCM. Looping sounds also
+//! carry a `smpl` chunk with the loop points, as read by samplers and
+//! engines.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::synth::looping::LoopRegion;
+
+const BITS_PER_SAMPLE: u16 = 16;
+/// Size of a `smpl` chunk holding a single loop, without its header.
+const SMPL_CHUNK_SIZE: u32 = 36 + 24;
+
+/// Writes the samples to a WAV file at `path`.
+pub fn save(path: impl AsRef<Path>, samples: &[f32], sample_rate: u32) -> anyhow::Result<()> {
+    save_looped(path, samples, sample_rate, None)
+}
+
+/// Writes the samples to a WAV file at `path`, storing the loop points
+/// when a loop region is given.
+pub fn save_looped(
+    path: impl AsRef<Path>,
+    samples: &[f32],
+    sample_rate: u32,
+    loop_region: Option<LoopRegion>,
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_looped(&mut writer, samples, sample_rate, loop_region)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes left and right frames to a stereo WAV file at `path`.
+pub fn save_stereo(
+    path: impl AsRef<Path>,
+    frames: &[[f32; 2]],
+    sample_rate: u32,
+) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_stereo(&mut writer, frames, sample_rate)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Encodes the samples as a WAV stream into `writer`.
+pub fn write(writer: &mut impl Write, samples: &[f32], sample_rate: u32) -> anyhow::Result<()> {
+    write_looped(writer, samples, sample_rate, None)
+}
+
+/// Encodes the samples as a WAV stream into `writer`, followed by a
+/// `smpl` chunk when a loop region is given.
+pub fn write_looped(
+    writer: &mut impl Write,
+    samples: &[f32],
+    sample_rate: u32,
+    loop_region: Option<LoopRegion>,
+) -> anyhow::Result<()> {
+    write_pcm(writer, samples, 1, sample_rate, loop_region)
+}
+
+/// Encodes left and right frames as a stereo WAV stream into `writer`.
+pub fn write_stereo(
+    writer: &mut impl Write,
+    frames: &[[f32; 2]],
+    sample_rate: u32,
+) -> anyhow::Result<()> {
+    write_pcm(writer, frames.as_flattened(), 2, sample_rate, None)
+}
+
+/// Encodes interleaved samples with the given number of channels.
+fn write_pcm(
+    writer: &mut impl Write,
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+    loop_region: Option<LoopRegion>,
+) -> anyhow::Result<()> {
+    let block_align = channels * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * block_align as u32;
+    let data_size = (samples.len() * (BITS_PER_SAMPLE / 8) as usize) as u32;
+    let smpl_size = if loop_region.is_some() {
+        8 + SMPL_CHUNK_SIZE
+    } else {
+        0
+    };
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size + smpl_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    // format chunk
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    // data chunk
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for &sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_all(&value.to_le_bytes())?;
+    }
+
+    if let Some(region) = loop_region {
+        write_smpl_chunk(writer, sample_rate, region)?;
+    }
+
+    Ok(())
+}
+
+fn write_smpl_chunk(
+    writer: &mut impl Write,
+    sample_rate: u32,
+    region: LoopRegion,
+) -> anyhow::Result<()> {
+    let sample_period = 1_000_000_000 / sample_rate;
+
+    writer.write_all(b"smpl")?;
+    writer.write_all(&SMPL_CHUNK_SIZE.to_le_bytes())?;
+    // manufacturer, product, sample period, MIDI unity note,
+    // pitch fraction, SMPTE format and offset
+    for value in [0, 0, sample_period, 60, 0, 0, 0] {
+        writer.write_all(&u32::to_le_bytes(value))?;
+    }
+    // one loop and no sampler specific data
+    writer.write_all(&1u32.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+
+    // cue point id, forward loop type, start, inclusive end,
+    // fraction and infinite play count
+    let end = region.end.saturating_sub(1) as u32;
+    for value in [0, 0, region.start as u32, end, 0, 0] {
+        writer.write_all(&u32::to_le_bytes(value))?;
+    }
+
+    Ok(())
+}
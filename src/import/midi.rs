@@ -0,0 +1,425 @@
+//! Standard MIDI File reader.
+//!
+//! Reads format 0 and 1 files and turns every note-on/note-off pair
+//! into a `NoteEvent` timed in seconds, following the tempo changes of
+//! the whole file. Notes still held at the end of the file end there.
+//! Controllers, pitch bends and system exclusive messages are skipped.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::music::NoteEvent;
+
+/// Tempo of a file without tempo events, 120 beats per minute.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Reads the MIDI file at `path`, returning its notes sorted by start
+/// time.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Vec<NoteEvent>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read(&mut reader)
+}
+
+/// Decodes a MIDI stream, returning its notes sorted by start time.
+pub fn read(reader: &mut impl Read) -> anyhow::Result<Vec<NoteEvent>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 14 || &bytes[0..4] != b"MThd" {
+        anyhow::bail!("Not a MIDI file");
+    }
+    let header_size = u32_at(&bytes, 4) as usize;
+    let format = u16_at(&bytes, 8);
+    let division = u16_at(&bytes, 12);
+    if format > 1 {
+        anyhow::bail!("Unsupported MIDI format {format}");
+    }
+
+    let mut events = Vec::new();
+    let mut offset = 8 + header_size;
+    let mut track = 0;
+    while offset + 8 <= bytes.len() {
+        let size = u32_at(&bytes, offset + 4) as usize;
+        let body = offset + 8;
+        let end = (body + size).min(bytes.len());
+        if &bytes[offset..offset + 4] == b"MTrk" {
+            read_track(&bytes[body..end], track, &mut events)?;
+            track += 1;
+        }
+        offset = body + size;
+    }
+
+    // merge the tracks, keeping the order of events within each track
+    events.sort_by_key(|event| event.tick);
+    let clock = Clock::new(division, &events);
+
+    let mut programs = [0u8; 16];
+    let mut open: HashMap<(u8, u8), Vec<(u64, u8)>> = HashMap::new();
+    let mut notes = Vec::new();
+    for event in &events {
+        match event.kind {
+            EventKind::Program { channel, program } => programs[channel as usize] = program,
+            EventKind::NoteOn {
+                channel,
+                key,
+                velocity,
+            } => open
+                .entry((channel, key))
+                .or_default()
+                .push((event.tick, velocity)),
+            EventKind::NoteOff { channel, key } => {
+                let Some(started) = open.get_mut(&(channel, key)) else {
+                    continue;
+                };
+                if started.is_empty() {
+                    continue;
+                }
+                // overlapping notes on the same key end in the order
+                // they started
+                let (tick, velocity) = started.remove(0);
+                let program = programs[channel as usize];
+                notes.push(note(
+                    &clock,
+                    channel,
+                    program,
+                    key,
+                    velocity,
+                    tick..event.tick,
+                ));
+            }
+            EventKind::Tempo(_) | EventKind::EndOfTrack => {}
+        }
+    }
+
+    // notes without a note-off last until the end of the file
+    let end = events.last().map_or(0, |event| event.tick);
+    let mut unterminated: Vec<_> = open.into_iter().collect();
+    unterminated.sort_by_key(|&(key, _)| key);
+    for ((channel, key), started) in unterminated {
+        for (tick, velocity) in started {
+            let program = programs[channel as usize];
+            notes.push(note(&clock, channel, program, key, velocity, tick..end));
+        }
+    }
+
+    notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(notes)
+}
+
+fn note(
+    clock: &Clock,
+    channel: u8,
+    program: u8,
+    key: u8,
+    velocity: u8,
+    ticks: Range<u64>,
+) -> NoteEvent {
+    let start = clock.seconds(ticks.start);
+    NoteEvent {
+        channel,
+        program,
+        key,
+        velocity: velocity as f32 / 127.0,
+        start: start as f32,
+        duration: (clock.seconds(ticks.end) - start) as f32,
+    }
+}
+
+struct Event {
+    tick: u64,
+    kind: EventKind,
+}
+
+enum EventKind {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+    },
+    Program {
+        channel: u8,
+        program: u8,
+    },
+    /// Microseconds per quarter note.
+    Tempo(u32),
+    EndOfTrack,
+}
+
+fn read_track(data: &[u8], track: usize, events: &mut Vec<Event>) -> anyhow::Result<()> {
+    let truncated = || anyhow::anyhow!("Truncated MIDI track {track}");
+
+    let mut position = 0;
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while position < data.len() {
+        tick += read_varint(data, &mut position).ok_or_else(truncated)? as u64;
+
+        let mut status = *data.get(position).ok_or_else(truncated)?;
+        if status < 0x80 {
+            // running status reuses the previous status byte
+            status = running_status.ok_or_else(|| anyhow::anyhow!("Missing status byte"))?;
+        } else {
+            position += 1;
+        }
+
+        match status {
+            0xff => {
+                let kind = *data.get(position).ok_or_else(truncated)?;
+                position += 1;
+                let length = read_varint(data, &mut position).ok_or_else(truncated)? as usize;
+                let body = data
+                    .get(position..position + length)
+                    .ok_or_else(truncated)?;
+                position += length;
+
+                match kind {
+                    0x2f => {
+                        events.push(Event {
+                            tick,
+                            kind: EventKind::EndOfTrack,
+                        });
+                        break;
+                    }
+                    0x51 if length == 3 => events.push(Event {
+                        tick,
+                        kind: EventKind::Tempo(u32::from_be_bytes([0, body[0], body[1], body[2]])),
+                    }),
+                    _ => {}
+                }
+            }
+            0xf0 | 0xf7 => {
+                let length = read_varint(data, &mut position).ok_or_else(truncated)? as usize;
+                position += length;
+            }
+            0x80..=0xef => {
+                running_status = Some(status);
+                let channel = status & 0x0f;
+                let length = match status & 0xf0 {
+                    0xc0 | 0xd0 => 1,
+                    _ => 2,
+                };
+                let body = data
+                    .get(position..position + length)
+                    .ok_or_else(truncated)?;
+                position += length;
+
+                let kind = match (status & 0xf0, body) {
+                    (0x90, &[key, velocity]) if velocity > 0 => EventKind::NoteOn {
+                        channel,
+                        key,
+                        velocity,
+                    },
+                    (0x80 | 0x90, &[key, _]) => EventKind::NoteOff { channel, key },
+                    (0xc0, &[program]) => EventKind::Program { channel, program },
+                    _ => continue,
+                };
+                events.push(Event { tick, kind });
+            }
+            _ => anyhow::bail!("Unexpected MIDI status {status:#04x}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts ticks to seconds through the tempo changes of a file.
+struct Clock {
+    /// Ticks per quarter note, or `None` for SMPTE timing.
+    ticks_per_quarter: Option<f64>,
+    /// Seconds per tick with SMPTE timing.
+    smpte_tick: f64,
+    /// Tick, time in seconds and tempo at every tempo change.
+    changes: Vec<(u64, f64, u32)>,
+}
+
+impl Clock {
+    fn new(division: u16, events: &[Event]) -> Self {
+        let mut clock = Clock {
+            ticks_per_quarter: None,
+            smpte_tick: 0.0,
+            changes: vec![(0, 0.0, DEFAULT_TEMPO)],
+        };
+
+        if division & 0x8000 != 0 {
+            // negative frames per second and ticks per frame
+            let fps = -((division >> 8) as u8 as i8) as f64;
+            let ticks_per_frame = (division & 0xff).max(1) as f64;
+            clock.smpte_tick = 1.0 / (fps.max(1.0) * ticks_per_frame);
+            return clock;
+        }
+
+        clock.ticks_per_quarter = Some(division.max(1) as f64);
+        for event in events {
+            if let EventKind::Tempo(tempo) = event.kind {
+                let seconds = clock.seconds(event.tick);
+                clock.changes.push((event.tick, seconds, tempo));
+            }
+        }
+        clock
+    }
+
+    fn seconds(&self, tick: u64) -> f64 {
+        let Some(ticks_per_quarter) = self.ticks_per_quarter else {
+            return tick as f64 * self.smpte_tick;
+        };
+
+        let index = self.changes.partition_point(|change| change.0 <= tick);
+        let (start, seconds, tempo) = self.changes[index.max(1) - 1];
+        let quarters = (tick - start) as f64 / ticks_per_quarter;
+        seconds + quarters * tempo as f64 / 1_000_000.0
+    }
+}
+
+/// Reads a variable length quantity, at most four bytes long.
+fn read_varint(data: &[u8], position: &mut usize) -> Option<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let byte = *data.get(*position)?;
+        *position += 1;
+        value = (value << 7) | (byte & 0x7f) as u32;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A format 0 file at 480 ticks per quarter holding one track.
+    fn file(track: &[u8]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend([0, 0, 0, 1, 0x01, 0xe0]);
+        bytes.extend(b"MTrk");
+        bytes.extend((track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+        bytes
+    }
+
+    fn notes(track: &[u8]) -> anyhow::Result<Vec<NoteEvent>> {
+        read(&mut file(track).as_slice())
+    }
+
+    #[test]
+    fn note_on_and_off() {
+        let notes = notes(&[
+            0x00, 0x90, 60, 100, // note on
+            0x83, 0x60, 0x80, 60, 0, // note off a quarter later
+            0x00, 0xff, 0x2f, 0x00,
+        ])
+        .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].key, 60);
+        assert_eq!(notes[0].start, 0.0);
+        assert!((notes[0].duration - 0.5).abs() < 1e-6);
+        assert!((notes[0].velocity - 100.0 / 127.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn running_status() {
+        let notes = notes(&[
+            0x00, 0x90, 60, 100, // note on
+            0x00, 64, 100, // second note on, running status
+            0x83, 0x60, 60, 0, // note offs as velocity 0, running status
+            0x00, 64, 0, //
+            0x00, 0xff, 0x2f, 0x00,
+        ])
+        .unwrap();
+        let keys: Vec<u8> = notes.iter().map(|note| note.key).collect();
+        assert_eq!(keys, [60, 64]);
+        assert!(notes.iter().all(|note| (note.duration - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn velocity_zero_ends_the_note() {
+        let notes = notes(&[
+            0x00, 0x90, 60, 100, //
+            0x81, 0x70, 0x90, 60, 0, // half a quarter later
+            0x00, 0xff, 0x2f, 0x00,
+        ])
+        .unwrap();
+        assert_eq!(notes.len(), 1);
+        assert!((notes[0].duration - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn unterminated_notes_end_with_the_file() {
+        let notes = notes(&[
+            0x00, 0x90, 60, 100, //
+            0x00, 0x90, 67, 100, //
+            0x83, 0x60, 0x80, 60, 0, //
+            0x83, 0x60, 0xff, 0x2f, 0x00, // end of track two quarters in
+        ])
+        .unwrap();
+        assert_eq!(notes.len(), 2);
+        let held = notes.iter().find(|note| note.key == 67).unwrap();
+        assert!((held.duration - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn truncated_events_are_errors() {
+        // note on missing its velocity
+        assert!(notes(&[0x00, 0x90, 60]).is_err());
+        // meta event longer than the track
+        assert!(notes(&[0x00, 0xff, 0x51, 0x03, 0x07]).is_err());
+        // delta time cut in the middle
+        assert!(notes(&[0x81]).is_err());
+        // data byte before any status
+        assert!(notes(&[0x00, 60, 100]).is_err());
+    }
+
+    #[test]
+    fn chunks_longer_than_the_file_are_clamped() {
+        let mut bytes = file(&[0x00, 0x90, 60, 100, 0x83, 0x60, 0x80, 60, 0]);
+        // declare a track larger than the bytes left
+        bytes[18..22].copy_from_slice(&1000u32.to_be_bytes());
+        let notes = read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(notes.len(), 1);
+    }
+
+    #[test]
+    fn odd_sized_unknown_chunks_are_skipped() {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend(6u32.to_be_bytes());
+        bytes.extend([0, 0, 0, 1, 0x01, 0xe0]);
+        // MIDI chunks have no padding byte, unlike RIFF
+        bytes.extend(b"XTRA");
+        bytes.extend(3u32.to_be_bytes());
+        bytes.extend([1, 2, 3]);
+        let track = [0x00, 0x90, 60, 100, 0x83, 0x60, 0x80, 60, 0];
+        bytes.extend(b"MTrk");
+        bytes.extend((track.len() as u32).to_be_bytes());
+        bytes.extend(track);
+        let notes = read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(notes.len(), 1);
+    }
+
+    #[test]
+    fn not_a_midi_file() {
+        assert!(read(&mut &b"RIFF"[..]).is_err());
+        assert!(read(&mut &b"MThd\0\0\0\x06\0\0"[..]).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! Minimal WAV reader for reference recordings.
+//!
+//! Reads integer PCM (8, 16, 24 and 32 bit) and 32-bit float files and
+//! mixes every channel down to mono.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Reads the WAV file at `path`, returning its mono samples and the
+/// sample rate.
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<(Vec<f32>, u32)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read(&mut reader)
+}
+
+/// Decodes a WAV stream, returning its mono samples and the sample rate.
+pub fn read(reader: &mut impl Read) -> anyhow::Result<(Vec<f32>, u32)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        anyhow::bail!("Not a WAV file");
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32_at(&bytes, offset + 4) as usize;
+        let body = offset + 8;
+        let end = (body + size).min(bytes.len());
+
+        match id {
+            b"fmt " => format = Some(Format::parse(&bytes[body..end])?),
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+
+        // chunks are padded to an even size
+        offset = body + size + (size & 1);
+    }
+
+    let format = format.ok_or_else(|| anyhow::Error::msg("Missing fmt chunk"))?;
+    let data = data.ok_or_else(|| anyhow::Error::msg("Missing data chunk"))?;
+    if format.sample_rate == 0 || format.channels == 0 {
+        anyhow::bail!(
+            "Invalid WAV header with {} channels at {} Hz",
+            format.channels,
+            format.sample_rate
+        );
+    }
+
+    let channels = format.channels as usize;
+    let width = (format.bits_per_sample / 8) as usize;
+    let decode: fn(&[u8]) -> f32 = match (format.tag, format.bits_per_sample) {
+        (FORMAT_PCM, 8) => |b: &[u8]| (b[0] as f32 - 128.0) / 128.0,
+        (FORMAT_PCM, 16) => |b: &[u8]| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (FORMAT_PCM, 24) => {
+            |b: &[u8]| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0
+        }
+        (FORMAT_PCM, 32) => {
+            |b: &[u8]| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0
+        }
+        (FORMAT_FLOAT, 32) => |b: &[u8]| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (tag, bits) => anyhow::bail!("Unsupported WAV format {tag} with {bits} bits"),
+    };
+
+    let samples = data
+        .chunks_exact(width * channels)
+        .map(|frame| {
+            let sum: f32 = frame.chunks_exact(width).map(decode).sum();
+            sum / channels as f32
+        })
+        .collect();
+
+    Ok((samples, format.sample_rate))
+}
+
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+}
+
+impl Format {
+    fn parse(chunk: &[u8]) -> anyhow::Result<Self> {
+        if chunk.len() < 16 {
+            anyhow::bail!("Truncated fmt chunk");
+        }
+
+        let mut tag = u16_at(chunk, 0);
+        if tag == FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+            // the sub-format GUID starts with the actual format tag
+            tag = u16_at(chunk, 24);
+        }
+
+        Ok(Format {
+            tag,
+            channels: u16_at(chunk, 2),
+            sample_rate: u32_at(chunk, 4),
+            bits_per_sample: u16_at(chunk, 14),
+        })
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    fn fmt(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let width = channels as u32 * bits as u32 / 8;
+        let mut body = Vec::new();
+        body.extend(tag.to_le_bytes());
+        body.extend(channels.to_le_bytes());
+        body.extend(sample_rate.to_le_bytes());
+        body.extend((sample_rate * width).to_le_bytes());
+        body.extend((width as u16).to_le_bytes());
+        body.extend(bits.to_le_bytes());
+        chunk(b"fmt ", &body)
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend((body.len() as u32 + 4).to_le_bytes());
+        bytes.extend(b"WAVE");
+        bytes.extend(body);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<(Vec<f32>, u32)> {
+        read(&mut &bytes[..])
+    }
+
+    #[test]
+    fn pcm_16() {
+        let data: Vec<u8> = [0i16, 16384, -32768]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let (samples, rate) = decode(&riff(&[
+            fmt(FORMAT_PCM, 1, 22050, 16),
+            chunk(b"data", &data),
+        ]))
+        .unwrap();
+        assert_eq!(rate, 22050);
+        assert_eq!(samples, [0.0, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn stereo_is_mixed_down() {
+        let data: Vec<u8> = [16384i16, 0, -16384, -16384]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let (samples, _) = decode(&riff(&[
+            fmt(FORMAT_PCM, 2, 44100, 16),
+            chunk(b"data", &data),
+        ]))
+        .unwrap();
+        assert_eq!(samples, [0.25, -0.5]);
+    }
+
+    #[test]
+    fn odd_sized_chunks_are_padded() {
+        let (samples, _) = decode(&riff(&[
+            fmt(FORMAT_PCM, 1, 8000, 8),
+            chunk(b"LIST", &[1, 2, 3]),
+            chunk(b"data", &[128, 192, 64]),
+        ]))
+        .unwrap();
+        assert_eq!(samples, [0.0, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn truncated_data_keeps_whole_frames() {
+        let mut bytes = riff(&[
+            fmt(FORMAT_PCM, 1, 8000, 16),
+            chunk(b"data", &[0, 64, 0, 32]),
+        ]);
+        // declare more data than there is, and cut the last sample
+        let length = bytes.len();
+        bytes[length - 8..length - 4].copy_from_slice(&100u32.to_le_bytes());
+        bytes.pop();
+        let (samples, _) = decode(&bytes).unwrap();
+        assert_eq!(samples, [0.5]);
+    }
+
+    #[test]
+    fn truncated_fmt_chunk_is_an_error() {
+        let mut bytes = riff(&[fmt(FORMAT_PCM, 1, 8000, 16)]);
+        bytes.truncate(bytes.len() - 4);
+        assert!(decode(&bytes).is_err());
+
+        let short = riff(&[chunk(b"fmt ", &[1, 0, 1, 0]), chunk(b"data", &[0, 0])]);
+        assert!(decode(&short).is_err());
+    }
+
+    #[test]
+    fn missing_chunks_are_errors() {
+        assert!(decode(&riff(&[fmt(FORMAT_PCM, 1, 8000, 16)])).is_err());
+        assert!(decode(&riff(&[chunk(b"data", &[0, 0])])).is_err());
+        assert!(decode(b"RIFF\0\0\0\0AVI ").is_err());
+        assert!(decode(b"RIFF").is_err());
+    }
+
+    #[test]
+    fn zero_rate_or_channels_are_errors() {
+        let zero_rate = riff(&[fmt(FORMAT_PCM, 1, 0, 16), chunk(b"data", &[0, 0])]);
+        assert!(decode(&zero_rate).is_err());
+
+        let zero_channels = riff(&[fmt(FORMAT_PCM, 0, 8000, 16), chunk(b"data", &[0, 0])]);
+        assert!(decode(&zero_channels).is_err());
+    }
+
+    #[test]
+    fn unsupported_formats_are_errors() {
+        let bytes = riff(&[fmt(FORMAT_PCM, 1, 8000, 12), chunk(b"data", &[0, 0])]);
+        assert!(decode(&bytes).is_err());
+    }
+}
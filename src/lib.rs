@@ -1,2 +1,6 @@
+pub mod analysis;
+pub mod export;
+pub mod import;
+pub mod music;
 pub mod sound;
 pub mod synth;
@@ -3,19 +3,102 @@ use std::sync::mpsc;
 
 use cpal::traits::StreamTrait;
 
-use refexer::sound::stream_setup;
-use refexer::synth::Synth;
+use refexer::analysis::matching::{MatchOptions, match_sound};
+use refexer::analysis::{Features, resample};
+use refexer::export::chip::{Format, RegisterStream};
+use refexer::export::code::{self, CodeForm, CodeOptions, SampleFormat, Source};
+use refexer::export::wav;
+use refexer::import::midi;
+use refexer::import::wav as wav_import;
+use refexer::music::jingle::Jingle;
+use refexer::music::{self, Instruments};
+use refexer::sound::stereo_stream_setup;
+use refexer::synth::chip::Chip;
+use refexer::synth::cleanup::{CleanupOptions, cleanup, cleanup_stereo};
+use refexer::synth::convolution::{ConvolutionOptions, ImpulseResponse, convolve};
+use refexer::synth::layered::LayeredSound;
+use refexer::synth::looping::{LoopRegion, find_loop};
 use refexer::synth::presets::{SoundType, SynthPreset};
+use refexer::synth::{SAMPLE_RATE, Synth};
 
 fn main() -> anyhow::Result<()> {
     // parse the command line and get the sound type
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: {} <sound_type>", args[0]);
-        eprintln!("Sound types: coin, shoot, explosion, powerup, hit, jump, blip");
-        process::exit(1);
+        usage(&args[0]);
     }
 
+    match args[1].as_str() {
+        "match" => match_command(&args),
+        "analyze" => analyze_command(&args),
+        "midi" => midi_command(&args),
+        "jingle" => jingle_command(&args),
+        "layers" => layers_command(&args),
+        _ => sound_command(&args),
+    }
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <sound_type> [--clean] [--hold <secs>] [--loop] [--loop-start <secs>] [--loop-end <secs>] [--chip <nes|gb>] [--registers <file>] [--code <file>] [--ir <file.wav>] [--wav <file>]",
+        program
+    );
+    eprintln!(
+        "       {} match <file.wav> [--iterations <n>] [--seed <n>] [--wav <file>]",
+        program
+    );
+    eprintln!("       {} analyze <sound_type|file.wav>", program);
+    eprintln!(
+        "       {} midi <file.mid> [--clean] [--instruments <file.json>] [--wav <file>]",
+        program
+    );
+    eprintln!(
+        "       {} jingle <pattern.toml> [--clean] [--wav <file>]",
+        program
+    );
+    eprintln!(
+        "       {} layers <sound.json> [--clean] [--code <file>] [--wav <file>]",
+        program
+    );
+    eprintln!("Sound types: coin, shoot, explosion, powerup, hit, jump, blip, bell");
+    eprintln!("  --clean       remove DC offset, trim silence and fade the edges, repeats");
+    eprintln!("                of single sounds fade around each restart too");
+    eprintln!("  --hold <secs> sustain the sound for the given time before releasing it");
+    eprintln!("  --loop        find a loop in the sustain and store or preview it");
+    eprintln!("  --loop-start <secs>  loop from this time instead of searching, implies --loop");
+    eprintln!("  --loop-end <secs>    loop up to this time, the end of the sound by default");
+    eprintln!("  --chip <name> restrict the sound to a sound chip: nes or gb");
+    eprintln!("  --registers <file>  write the register writes per frame, needs --chip");
+    eprintln!("  --format <name>     register table format: ca65, rgbds or c");
+    eprintln!("  --code <file>       write the samples as a C header (.h) or Rust source (.rs)");
+    eprintln!("  --rate <hz>         sample rate of --code, 1000 to 44100");
+    eprintln!("  --bits <format>     sample format of --code: i8, u8 or i16");
+    eprintln!("  --const-params      write --code as a const SynthParams instead of samples");
+    eprintln!("  --ir <file>   convolve the sound with an impulse response WAV");
+    eprintln!("  --ir-mix <n>  wet/dry balance of the impulse response, 0..1");
+    eprintln!("  --pre-delay <secs>  delay of the impulse response");
+    eprintln!("  --wav <file>  write the sound to a WAV file instead of playing it");
+    eprintln!("  --instruments <file>  JSON patches for the MIDI channels and programs");
+    process::exit(1);
+}
+
+/// Returns the value following an option, exiting when it is missing
+/// or can't be parsed.
+fn option_value<'a, T: std::str::FromStr>(
+    options: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> T {
+    match options.next().map(|value| value.parse()) {
+        Some(Ok(value)) => value,
+        _ => {
+            eprintln!("Missing or invalid value after {}", option);
+            process::exit(1);
+        }
+    }
+}
+
+/// Generates a sound of the given type and plays or saves it.
+fn sound_command(args: &[String]) -> anyhow::Result<()> {
     let sound_type = match SoundType::try_from(args[1].as_str()) {
         Ok(st) => st,
         Err(e) => {
@@ -24,24 +107,361 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
+    let mut clean = false;
+    let mut hold: Option<f32> = None;
+    let mut looping = false;
+    let mut loop_start: Option<f32> = None;
+    let mut loop_end: Option<f32> = None;
+    let mut chip: Option<Chip> = None;
+    let mut registers_path: Option<String> = None;
+    let mut format: Option<Format> = None;
+    let mut code_path: Option<String> = None;
+    let mut code_options = CodeOptions::default();
+    let mut const_params = false;
+    let mut ir_path: Option<String> = None;
+    let mut convolution = ConvolutionOptions::default();
+    let mut wav_path: Option<String> = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--clean" => clean = true,
+            "--loop" => looping = true,
+            "--hold" => hold = Some(option_value(&mut options, option)),
+            "--loop-start" => loop_start = Some(option_value(&mut options, option)),
+            "--loop-end" => loop_end = Some(option_value(&mut options, option)),
+            "--chip" => {
+                let name: String = option_value(&mut options, option);
+                chip = Some(Chip::try_from(name.as_str()).map_err(anyhow::Error::msg)?);
+            }
+            "--registers" => registers_path = Some(option_value(&mut options, option)),
+            "--format" => {
+                let name: String = option_value(&mut options, option);
+                format = Some(Format::try_from(name.as_str()).map_err(anyhow::Error::msg)?);
+            }
+            "--code" => code_path = Some(option_value(&mut options, option)),
+            "--rate" => code_options.set_sample_rate(option_value(&mut options, option))?,
+            "--bits" => {
+                let name: String = option_value(&mut options, option);
+                code_options.format =
+                    SampleFormat::try_from(name.as_str()).map_err(anyhow::Error::msg)?;
+            }
+            "--const-params" => const_params = true,
+            "--ir" => ir_path = Some(option_value(&mut options, option)),
+            "--ir-mix" => convolution.mix = option_value(&mut options, option),
+            "--pre-delay" => convolution.pre_delay = option_value(&mut options, option),
+            "--wav" => wav_path = Some(option_value(&mut options, option)),
+            _ => eprintln!("Warning: ignoring unknown option {}", option),
+        }
+    }
+
     // create the correct preset for the selected sound type
     let mut preset = SynthPreset::new();
-    let params = preset.generate(sound_type);
+    preset.set_chip(chip);
+    let mut params = preset.generate(sound_type);
+    params.env_hold = hold.is_some();
 
-    // create the synth from params and pass it to the stream
+    if let Some(path) = registers_path {
+        let Some(chip) = chip else {
+            anyhow::bail!("--registers needs a --chip");
+        };
+        let format = format.unwrap_or(match chip {
+            Chip::Nes => Format::Ca65,
+            Chip::GameBoy => Format::Rgbds,
+        });
+        let stream = RegisterStream::capture(&params, chip);
+        for feature in &stream.unsupported {
+            eprintln!("Warning: the {} lacks {}", chip.name(), feature);
+        }
+        let name = std::path::Path::new(&path)
+            .file_stem()
+            .map_or("sound".into(), |stem| stem.to_string_lossy());
+        stream.save(&path, format, &name)?;
+        println!("Saved {} frames to {}", stream.frames.len(), path);
+        return Ok(());
+    }
+
+    // create the synth from params and render the whole sound
+    let cleanup_options = CleanupOptions::default();
     let mut synth = Synth::new(params);
-    synth.play_sample();
+    if clean {
+        synth.set_repeat_fade(cleanup_options.repeat_fade);
+    }
+    let mut sustain = synth.sustain_range();
+    let mut data = match hold {
+        Some(secs) => {
+            let hold = (secs * SAMPLE_RATE as f32) as usize;
+            sustain.end = hold.max(sustain.start);
+            synth.render_held(hold)
+        }
+        None => synth.render(),
+    };
+    if let Some(path) = ir_path {
+        let ir = ImpulseResponse::load(&path)?;
+        data = convolve(&data, &ir, &convolution);
+    }
+    if clean {
+        let trimmed = cleanup(&mut data, &cleanup_options);
+        sustain = sustain.start.saturating_sub(trimmed)..sustain.end.saturating_sub(trimmed);
+    }
+
+    let loop_region = match (loop_start, loop_end) {
+        (None, None) if looping => {
+            let region = find_loop(&data, sustain);
+            if region.is_none() {
+                eprintln!("Warning: no loop found in the sustain of the sound");
+            }
+            region
+        }
+        (None, None) => None,
+        (start, end) => {
+            let sample = |secs: f32| (secs.max(0.0) * SAMPLE_RATE as f32) as usize;
+            let start = start.map_or(0, sample);
+            let end = end.map_or(data.len(), sample).min(data.len());
+            let region = LoopRegion::new(start, end);
+            if region.is_none() {
+                eprintln!("Warning: the loop ends before it starts, ignoring it");
+            }
+            region
+        }
+    };
+    if let Some(region) = loop_region {
+        println!("Loop from sample {} to {}", region.start, region.end);
+    }
+
+    if let Some(path) = code_path {
+        let form = match CodeForm::from_path(&path) {
+            _ if const_params => CodeForm::RustParams,
+            Some(form) => form,
+            None => anyhow::bail!("Unknown code file {}, use .h or .rs", path),
+        };
+        code::save(&path, form, &data, Source::Params(&params), &code_options)?;
+        println!("Saved {:?} to {}", form, path);
+        return Ok(());
+    }
+
+    if let Some(path) = wav_path {
+        wav::save_looped(&path, &data, SAMPLE_RATE, loop_region)?;
+        println!("Saved {} samples to {}", data.len(), path);
+        return Ok(());
+    }
+
+    // preview the loop by repeating it for a couple of seconds
+    if let Some(region) = loop_region {
+        let repeats = 2 * SAMPLE_RATE as usize / region.len();
+        data = region.unroll(&data, repeats.clamp(1, 64));
+    }
+
+    play(data)
+}
+
+/// Searches the parameters that best imitate a WAV file and prints the
+/// closest candidates.
+fn match_command(args: &[String]) -> anyhow::Result<()> {
+    let Some(target_path) = args.get(2) else {
+        usage(&args[0]);
+    };
+
+    let mut match_options = MatchOptions::default();
+    let mut wav_path: Option<String> = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--iterations" => match_options.iterations = option_value(&mut options, option),
+            "--seed" => match_options.seed = option_value(&mut options, option),
+            "--wav" => wav_path = Some(option_value(&mut options, option)),
+            _ => eprintln!("Warning: ignoring unknown option {}", option),
+        }
+    }
+
+    let (target, sample_rate) = wav_import::load(target_path)?;
+    let target = resample(&target, sample_rate, SAMPLE_RATE);
+
+    let results = match_sound(&target, &match_options);
+    for (i, result) in results.iter().enumerate() {
+        println!(
+            "#{} {:?} distance {:.4}",
+            i + 1,
+            result.params.wave_type,
+            result.distance
+        );
+        println!("{}", serde_json::to_string_pretty(&result.params)?);
+    }
+
+    if let (Some(path), Some(best)) = (wav_path, results.first()) {
+        let data = Synth::new(best.params).render();
+        wav::save(&path, &data, SAMPLE_RATE)?;
+        println!("Saved the closest match to {}", path);
+    }
+
+    Ok(())
+}
+
+/// Prints the features of a WAV file or of a freshly generated sound.
+fn analyze_command(args: &[String]) -> anyhow::Result<()> {
+    let Some(source) = args.get(2) else {
+        usage(&args[0]);
+    };
+
+    let data = if source.to_lowercase().ends_with(".wav") {
+        let (data, sample_rate) = wav_import::load(source)?;
+        resample(&data, sample_rate, SAMPLE_RATE)
+    } else {
+        let sound_type = SoundType::try_from(source.as_str()).map_err(anyhow::Error::msg)?;
+        let params = SynthPreset::new().generate(sound_type);
+        Synth::new(params).render()
+    };
+
+    let features = Features::new(&data);
+    println!("{}", serde_json::to_string_pretty(&features)?);
+
+    Ok(())
+}
+
+/// Renders a MIDI file with synth patches and plays or saves it.
+fn midi_command(args: &[String]) -> anyhow::Result<()> {
+    let Some(midi_path) = args.get(2) else {
+        usage(&args[0]);
+    };
+
+    let mut clean = false;
+    let mut instruments = Instruments::default();
+    let mut wav_path: Option<String> = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--clean" => clean = true,
+            "--instruments" => {
+                let path: String = option_value(&mut options, option);
+                instruments = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            }
+            "--wav" => wav_path = Some(option_value(&mut options, option)),
+            _ => eprintln!("Warning: ignoring unknown option {}", option),
+        }
+    }
 
+    let notes = midi::load(midi_path)?;
+    let mut data = music::render(&notes, &instruments);
+    println!("Rendered {} notes", notes.len());
+    if clean {
+        cleanup(&mut data, &CleanupOptions::default());
+    }
+
+    if let Some(path) = wav_path {
+        wav::save(&path, &data, SAMPLE_RATE)?;
+        println!("Saved {} samples to {}", data.len(), path);
+        return Ok(());
+    }
+
+    play(data)
+}
+
+/// Renders a jingle pattern and plays or saves it.
+fn jingle_command(args: &[String]) -> anyhow::Result<()> {
+    let Some(pattern_path) = args.get(2) else {
+        usage(&args[0]);
+    };
+
+    let mut clean = false;
+    let mut wav_path: Option<String> = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--clean" => clean = true,
+            "--wav" => wav_path = Some(option_value(&mut options, option)),
+            _ => eprintln!("Warning: ignoring unknown option {}", option),
+        }
+    }
+
+    let mut data = Jingle::load(pattern_path)?.render();
+    if clean {
+        cleanup(&mut data, &CleanupOptions::default());
+    }
+
+    if let Some(path) = wav_path {
+        wav::save(&path, &data, SAMPLE_RATE)?;
+        println!("Saved {} samples to {}", data.len(), path);
+        return Ok(());
+    }
+
+    play(data)
+}
+
+/// Renders a layered sound saved as JSON and plays or saves it in
+/// stereo, or exports its mono mix as source code.
+fn layers_command(args: &[String]) -> anyhow::Result<()> {
+    let Some(sound_path) = args.get(2) else {
+        usage(&args[0]);
+    };
+
+    let mut clean = false;
+    let mut code_path: Option<String> = None;
+    let mut code_options = CodeOptions::default();
+    let mut wav_path: Option<String> = None;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--clean" => clean = true,
+            "--code" => code_path = Some(option_value(&mut options, option)),
+            "--rate" => code_options.set_sample_rate(option_value(&mut options, option))?,
+            "--bits" => {
+                let name: String = option_value(&mut options, option);
+                code_options.format =
+                    SampleFormat::try_from(name.as_str()).map_err(anyhow::Error::msg)?;
+            }
+            "--const-params" => {
+                anyhow::bail!("Layered sounds have no const params, export their samples")
+            }
+            "--registers" => anyhow::bail!(
+                "Register tables need a single sound, layered sounds play several channels"
+            ),
+            "--wav" => wav_path = Some(option_value(&mut options, option)),
+            _ => eprintln!("Warning: ignoring unknown option {}", option),
+        }
+    }
+
+    let layered = LayeredSound::load(sound_path)?;
+
+    if let Some(path) = code_path {
+        let Some(form) = CodeForm::from_path(&path) else {
+            anyhow::bail!("Unknown code file {}, use .h or .rs", path);
+        };
+        let mut data = layered.render();
+        if clean {
+            cleanup(&mut data, &CleanupOptions::default());
+        }
+        code::save(&path, form, &data, Source::Layers(&layered), &code_options)?;
+        println!("Saved {:?} to {}", form, path);
+        return Ok(());
+    }
+
+    let mut frames = layered.render_stereo();
+    if clean {
+        cleanup_stereo(&mut frames, &CleanupOptions::default());
+    }
+
+    if let Some(path) = wav_path {
+        wav::save_stereo(&path, &frames, SAMPLE_RATE)?;
+        println!("Saved {} frames to {}", frames.len(), path);
+        return Ok(());
+    }
+
+    play_stereo(frames)
+}
+
+/// Plays a rendered sound on the default output device and waits for
+/// it to finish.
+fn play(data: Vec<f32>) -> anyhow::Result<()> {
+    play_stereo(data.into_iter().map(|sample| [sample, sample]).collect())
+}
+
+/// Plays left and right frames and waits for them to finish.
+fn play_stereo(data: Vec<[f32; 2]>) -> anyhow::Result<()> {
     let (tx, rx) = mpsc::channel();
-    let (stream, sample_rate) = stream_setup(rx)?;
+    let (stream, sample_rate) = stereo_stream_setup(rx)?;
 
     stream.play()?;
 
-    // generate sound data and send
-    let mut data = Vec::new();
-    while let Some(value) = synth.synth_sample() {
-        data.push(value);
-    }
+    // send the sound data to the stream
     let length = data.len();
     tx.send(data)?;
 
@@ -0,0 +1,156 @@
+//! Music played with sound effect patches.
+//!
+//! Notes, whatever their source, are rendered one voice each with the
+//! patch picked for their channel or program, tuned to their key, and
+//! mixed down to a single buffer.
+
+pub mod jingle;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::synth::params::{SynthParams, WaveType};
+use crate::synth::pitch::Note;
+use crate::synth::{SAMPLE_RATE, Synth};
+
+/// Channel of the drums in General MIDI, counted from zero.
+pub const DRUM_CHANNEL: u8 = 9;
+
+/// A note to play.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteEvent {
+    /// Channel counted from zero.
+    pub channel: u8,
+    /// Program selected on the channel when the note started.
+    pub program: u8,
+    /// MIDI note number, 60 is middle C.
+    pub key: u8,
+    /// Loudness, 0..1.
+    pub velocity: f32,
+    /// Start time in seconds.
+    pub start: f32,
+    /// Time until the note is released, in seconds.
+    pub duration: f32,
+}
+
+/// Patches used to play each channel and program.
+///
+/// Patches with `env_hold` set sustain until the note ends, the others
+/// play as one-shots whatever the length of the note.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Instruments {
+    /// Patch of the notes matching no other entry.
+    pub default: SynthParams,
+    /// Patches by program number.
+    pub programs: BTreeMap<u8, SynthParams>,
+    /// Patches by channel, preferred over the program ones.
+    pub channels: BTreeMap<u8, SynthParams>,
+}
+
+impl Default for Instruments {
+    fn default() -> Self {
+        let mut lead = SynthParams::new();
+        lead.env_sustain = 0.2;
+        lead.env_decay = 0.3;
+        lead.env_hold = true;
+
+        let mut drum = SynthParams::new();
+        drum.wave_type = WaveType::Noise;
+        drum.env_sustain = 0.05;
+        drum.env_decay = 0.25;
+        drum.env_punch = 0.3;
+
+        Instruments {
+            default: lead,
+            programs: BTreeMap::new(),
+            channels: BTreeMap::from([(DRUM_CHANNEL, drum)]),
+        }
+    }
+}
+
+impl Instruments {
+    /// Patch playing a note on `channel` with `program` selected.
+    pub fn patch(&self, channel: u8, program: u8) -> SynthParams {
+        self.channels
+            .get(&channel)
+            .or_else(|| self.programs.get(&program))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Renders every note with its patch and mixes them down. The mix is
+/// scaled down when it would clip.
+pub fn render(notes: &[NoteEvent], instruments: &Instruments) -> Vec<f32> {
+    let mut mix = Vec::new();
+    for note in notes {
+        let params = instruments
+            .patch(note.channel, note.program)
+            .with_note(Note::new(note.key as i32, 0.0), SAMPLE_RATE);
+
+        let voice = render_voice(params, Some(note.duration));
+        mix_voice(&mut mix, &voice, note.start, note.velocity);
+    }
+
+    normalize(&mut mix);
+    mix
+}
+
+/// Plays a patch for `duration` seconds, or as a one-shot when it
+/// doesn't hold or has no duration.
+fn render_voice(params: SynthParams, duration: Option<f32>) -> Vec<f32> {
+    let mut synth = Synth::new(params);
+    match duration {
+        Some(duration) if params.env_hold => {
+            synth.render_held((duration.max(0.0) * SAMPLE_RATE as f32) as usize)
+        }
+        _ => synth.render(),
+    }
+}
+
+/// Adds a voice starting at `start` seconds into the mix, growing it as
+/// needed.
+fn mix_voice(mix: &mut Vec<f32>, voice: &[f32], start: f32, gain: f32) {
+    let start = (start.max(0.0) * SAMPLE_RATE as f32) as usize;
+    mix_at(mix, voice, start, gain);
+}
+
+/// A frame of a mix, mono or stereo.
+pub(crate) trait Frame: Copy + Default {
+    /// Adds a mono sample scaled by the gain of each channel.
+    fn add(&mut self, sample: f32, gain: Self);
+}
+
+impl Frame for f32 {
+    fn add(&mut self, sample: f32, gain: Self) {
+        *self += sample * gain;
+    }
+}
+
+impl Frame for [f32; 2] {
+    fn add(&mut self, sample: f32, gain: Self) {
+        self[0] += sample * gain[0];
+        self[1] += sample * gain[1];
+    }
+}
+
+/// Adds a mono voice starting at frame `offset` into the mix, growing
+/// it as needed.
+pub(crate) fn mix_at<F: Frame>(mix: &mut Vec<F>, voice: &[f32], offset: usize, gain: F) {
+    if mix.len() < offset + voice.len() {
+        mix.resize(offset + voice.len(), F::default());
+    }
+    for (out, &sample) in mix[offset..].iter_mut().zip(voice) {
+        out.add(sample, gain);
+    }
+}
+
+/// Scales the mix down when its peak is above full scale.
+fn normalize(mix: &mut [f32]) {
+    let peak = mix.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak > 1.0 {
+        mix.iter_mut().for_each(|s| *s /= peak);
+    }
+}
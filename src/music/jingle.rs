@@ -0,0 +1,399 @@
+//! Short sequences of sound effects, such as level complete jingles.
+//!
+//! A jingle is a timeline of patches, each started at its own time and
+//! optionally retuned to a note. Jingles can be built in code or read
+//! from a TOML pattern like this one:
+//!
+//! ```toml
+//! tempo = 150.0 # optional, times and lengths are in beats when set
+//!
+//! [patches]
+//! coin = { preset = "coin", seed = 7 }
+//! lead = { wave_type = "Square", env_sustain = 0.2, env_decay = 0.3, env_hold = true }
+//!
+//! [[events]]
+//! patch = "coin"
+//! time = 0.0
+//!
+//! [[events]]
+//! patch = "lead"
+//! time = 1.0
+//! note = "C5"   # or a MIDI note number
+//! length = 2.0  # held patches release after this time
+//! volume = 0.8
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{mix_voice, normalize, render_voice};
+use crate::synth::SAMPLE_RATE;
+use crate::synth::params::SynthParams;
+use crate::synth::pitch::Note;
+use crate::synth::presets::{SoundType, SynthPreset};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JingleEvent {
+    pub params: SynthParams,
+    /// Start time in seconds.
+    pub start: f32,
+    /// Note replacing the pitch of the patch.
+    pub pitch: Option<Note>,
+    /// Time until held patches are released, in seconds. Without it
+    /// they release where their sustain ends.
+    pub length: Option<f32>,
+    pub volume: f32,
+}
+
+impl JingleEvent {
+    pub fn new(params: SynthParams, start: f32) -> Self {
+        JingleEvent {
+            params,
+            start,
+            pitch: None,
+            length: None,
+            volume: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Jingle {
+    pub events: Vec<JingleEvent>,
+}
+
+impl Jingle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the TOML pattern at `path`.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a TOML pattern, see the module documentation.
+    pub fn from_toml(text: &str) -> anyhow::Result<Self> {
+        let pattern: Pattern = toml::from_str(text)?;
+        let beat = pattern.tempo.map_or(1.0, |tempo| 60.0 / tempo);
+
+        let mut patches = HashMap::new();
+        for (name, patch) in pattern.patches {
+            let params = match patch {
+                PatchSource::Preset { preset, seed } => {
+                    let sound_type =
+                        SoundType::try_from(preset.as_str()).map_err(anyhow::Error::msg)?;
+                    SynthPreset::with_seed(seed).generate(sound_type)
+                }
+                PatchSource::Params(table) => inline_params(&name, table)?,
+            };
+            patches.insert(name, params);
+        }
+
+        let mut jingle = Jingle::new();
+        for event in pattern.events {
+            let Some(&params) = patches.get(&event.patch) else {
+                anyhow::bail!("Unknown patch '{}'", event.patch);
+            };
+            let pitch = match event.note {
+                None => None,
+                Some(NoteValue::Midi(midi)) => Some(Note::new(midi, 0.0)),
+                Some(NoteValue::Name(name)) => Some(
+                    Note::from_name(&name)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid note name '{name}'"))?,
+                ),
+            };
+
+            jingle.events.push(JingleEvent {
+                params,
+                start: event.time * beat,
+                pitch,
+                length: event.length.map(|length| length * beat),
+                volume: event.volume,
+            });
+        }
+
+        Ok(jingle)
+    }
+
+    /// Renders every event and mixes them down. The mix is scaled down
+    /// when it would clip.
+    pub fn render(&self) -> Vec<f32> {
+        let mut mix = Vec::new();
+        for event in &self.events {
+            let params = match event.pitch {
+                Some(note) => event.params.with_note(note, SAMPLE_RATE),
+                None => event.params,
+            };
+
+            let voice = render_voice(params, event.length);
+            mix_voice(&mut mix, &voice, event.start, event.volume);
+        }
+
+        normalize(&mut mix);
+        mix
+    }
+}
+
+#[derive(Deserialize)]
+struct Pattern {
+    tempo: Option<f32>,
+    #[serde(default)]
+    patches: HashMap<String, PatchSource>,
+    #[serde(default)]
+    events: Vec<PatternEvent>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum PatchSource {
+    /// A sound from a generator, the same for the same seed.
+    Preset {
+        preset: String,
+        #[serde(default)]
+        seed: u64,
+    },
+    /// Params spelled out, any missing one keeps its default. The keys
+    /// are checked by `inline_params`, as `SynthParams` ignores unknown
+    /// ones.
+    Params(toml::Table),
+}
+
+#[derive(Deserialize)]
+struct PatternEvent {
+    patch: String,
+    time: f32,
+    note: Option<NoteValue>,
+    length: Option<f32>,
+    #[serde(default = "full_volume")]
+    volume: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NoteValue {
+    Midi(i32),
+    Name(String),
+}
+
+/// Reads the params of an inline patch, reporting misspelled keys that
+/// would otherwise leave their param at its default.
+fn inline_params(name: &str, table: toml::Table) -> anyhow::Result<SynthParams> {
+    // a preset with a misspelled key lands here, not in `Preset`
+    if table.contains_key("preset") {
+        match table
+            .keys()
+            .find(|key| !["preset", "seed"].contains(&key.as_str()))
+        {
+            Some(key) => anyhow::bail!("Unknown key '{key}' in preset patch '{name}'"),
+            None => anyhow::bail!("Invalid preset or seed in patch '{name}'"),
+        }
+    }
+
+    // the params as read fill in the optional tables, such as `filter`,
+    // which are null in the defaults
+    let params: Result<SynthParams, _> = table.clone().try_into();
+    let defaults = serde_json::to_value(params.as_ref().copied().unwrap_or_default())?;
+    if let Some(key) = unknown_key(&table, &defaults) {
+        anyhow::bail!("Unknown param '{key}' in patch '{name}'");
+    }
+    Ok(params?)
+}
+
+/// First key of `table` missing from the object `defaults`, searching
+/// the nested tables and arrays too, as a dotted path.
+fn unknown_key(table: &toml::Table, defaults: &serde_json::Value) -> Option<String> {
+    let defaults = defaults.as_object()?;
+    for (key, value) in table {
+        let Some(default) = defaults.get(key) else {
+            return Some(key.clone());
+        };
+        if let Some(nested) = unknown_nested_key(value, default) {
+            return Some(format!("{key}{nested}"));
+        }
+    }
+    None
+}
+
+/// Unknown key inside a table or the elements of an array, as the rest
+/// of the path after the key holding `value`. Elements past the end of
+/// the defaults are checked against the first one.
+fn unknown_nested_key(value: &toml::Value, default: &serde_json::Value) -> Option<String> {
+    match value {
+        toml::Value::Table(table) => unknown_key(table, default).map(|key| format!(".{key}")),
+        toml::Value::Array(values) => {
+            let defaults = default.as_array()?;
+            values.iter().enumerate().find_map(|(i, value)| {
+                let default = defaults.get(i).or(defaults.first())?;
+                unknown_nested_key(value, default).map(|nested| format!("[{i}]{nested}"))
+            })
+        }
+        _ => None,
+    }
+}
+
+fn full_volume() -> f32 {
+    1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::params::WaveType;
+
+    const PATTERN: &str = r#"
+        tempo = 120.0
+
+        [patches]
+        coin = { preset = "coin", seed = 7 }
+        lead = { wave_type = "Sine", env_sustain = 0.2, env_hold = true }
+
+        [[events]]
+        patch = "coin"
+        time = 0.0
+
+        [[events]]
+        patch = "lead"
+        time = 1.0
+        note = "A4"
+        length = 2.0
+        volume = 0.5
+
+        [[events]]
+        patch = "lead"
+        time = 2.0
+        note = 60
+    "#;
+
+    #[test]
+    fn pattern_in_beats() {
+        let jingle = Jingle::from_toml(PATTERN).unwrap();
+        assert_eq!(jingle.events.len(), 3);
+
+        let [coin, lead, midi] = &jingle.events[..] else {
+            unreachable!()
+        };
+        assert_eq!(coin.start, 0.0);
+        assert_eq!(coin.volume, 1.0);
+        assert_eq!(coin.pitch, None);
+
+        assert_eq!(lead.params.wave_type, WaveType::Sine);
+        assert_eq!(lead.params.env_sustain, 0.2);
+        assert!(lead.params.env_hold);
+        assert_eq!(lead.params.env_decay, SynthParams::default().env_decay);
+        assert_eq!(lead.start, 0.5);
+        assert_eq!(lead.length, Some(1.0));
+        assert_eq!(lead.volume, 0.5);
+        assert_eq!(lead.pitch, Note::from_name("A4"));
+
+        assert_eq!(midi.start, 1.0);
+        assert_eq!(midi.pitch, Some(Note::new(60, 0.0)));
+    }
+
+    #[test]
+    fn presets_depend_on_the_seed_only() {
+        let a = Jingle::from_toml(PATTERN).unwrap();
+        let b = Jingle::from_toml(PATTERN).unwrap();
+        assert_eq!(a.events[0].params, b.events[0].params);
+        assert_eq!(
+            a.events[0].params,
+            SynthPreset::with_seed(7).generate(SoundType::PickupCoin)
+        );
+    }
+
+    #[test]
+    fn misspelled_params_are_errors() {
+        let error = Jingle::from_toml(
+            r#"
+            [patches]
+            lead = { wave_type = "Sine", env_sustian = 0.2 }
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("env_sustian"), "{error}");
+
+        let error = Jingle::from_toml(
+            r#"
+            [patches]
+            bell = { wave_type = "Fm", fm = { ratoi = 3.5 } }
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("fm.ratoi"), "{error}");
+    }
+
+    #[test]
+    fn presets_with_extra_keys_are_errors() {
+        let pattern = r#"
+            [patches]
+            coin = { preset = "coin", sead = 7 }
+        "#;
+        let error = Jingle::from_toml(pattern).unwrap_err().to_string();
+        assert!(error.contains("'sead'"), "{error}");
+    }
+
+    #[test]
+    fn misspelled_optional_params_are_errors() {
+        for (patch, key) in [
+            ("filter = { cutof = 0.5 }", "filter.cutof"),
+            ("ring_mod = { ratoi = 2.0 }", "ring_mod.ratoi"),
+            ("arpeggio = { stpes = [0.0, 12.0] }", "arpeggio.stpes"),
+            (
+                "volume_env = [{ time = 0.0, level = 1.0 }, { tiem = 0.5 }]",
+                "volume_env[1].tiem",
+            ),
+            ("lfos = [{}, { rtae = 4.0 }]", "lfos[1].rtae"),
+        ] {
+            let pattern = format!("[patches]\nlead = {{ wave_type = \"Sine\", {patch} }}");
+            let error = Jingle::from_toml(&pattern).unwrap_err().to_string();
+            assert!(error.contains(key), "{error}");
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        let unknown_patch = r#"
+            [[events]]
+            patch = "missing"
+            time = 0.0
+        "#;
+        assert!(Jingle::from_toml(unknown_patch).is_err());
+
+        let unknown_preset = r#"
+            [patches]
+            x = { preset = "kazoo" }
+        "#;
+        assert!(Jingle::from_toml(unknown_preset).is_err());
+
+        let bad_note = r#"
+            [patches]
+            lead = { wave_type = "Sine" }
+
+            [[events]]
+            patch = "lead"
+            time = 0.0
+            note = "H9"
+        "#;
+        assert!(Jingle::from_toml(bad_note).is_err());
+    }
+
+    #[test]
+    fn seconds_without_tempo() {
+        let jingle = Jingle::from_toml(
+            r#"
+            [patches]
+            lead = { wave_type = "Sine" }
+
+            [[events]]
+            patch = "lead"
+            time = 1.5
+            length = 0.25
+            "#,
+        )
+        .unwrap();
+        assert_eq!(jingle.events[0].start, 1.5);
+        assert_eq!(jingle.events[0].length, Some(0.25));
+    }
+}
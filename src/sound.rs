@@ -1,23 +1,72 @@
 use cpal::traits::{DeviceTrait, HostTrait};
+use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 
+use crate::synth::Synth;
+use crate::synth::live::LiveParam;
+use crate::synth::params::SynthParams;
+use crate::synth::sample::Sample;
+use crate::synth::wavetable::Cycle;
+
+/// Commands sent to a live synth running inside the audio stream.
+pub enum SynthCommand {
+    /// Starts a new sound, replacing the one currently playing.
+    Play(Box<SynthParams>),
+    /// Sets the sample and the imported cycle of the next sounds.
+    Sources(Option<Arc<Sample>>, Option<Arc<Cycle>>),
+    /// Glides a parameter of the playing sound to a new value.
+    SetLive(LiveParam, f32),
+    /// Releases a held sound and lets it decay.
+    Release,
+    /// Silences the current sound immediately.
+    Stop,
+}
+
+/// Sets up a stream that plays the pre-rendered buffers it receives.
 pub fn stream_setup(
     receiver: Receiver<Vec<f32>>,
 ) -> anyhow::Result<(cpal::Stream, cpal::SampleRate)> {
+    source_stream_setup(SynthReceiver::new(receiver))
+}
+
+/// Sets up a stream that plays pre-rendered left and right frames.
+pub fn stereo_stream_setup(
+    receiver: Receiver<Vec<[f32; 2]>>,
+) -> anyhow::Result<(cpal::Stream, cpal::SampleRate)> {
+    source_stream_setup(SynthReceiver::new(receiver))
+}
+
+/// Sets up a stream that synthesizes sounds in realtime, driven by the
+/// commands it receives. Use this for sounds held until release.
+pub fn live_stream_setup(
+    receiver: Receiver<SynthCommand>,
+) -> anyhow::Result<(cpal::Stream, cpal::SampleRate)> {
+    let source = LiveSynth {
+        receiver,
+        synth: Synth::new(SynthParams::default()),
+    };
+
+    source_stream_setup(source)
+}
+
+fn source_stream_setup<S>(source: S) -> anyhow::Result<(cpal::Stream, cpal::SampleRate)>
+where
+    S: SampleSource + Send + 'static,
+{
     let (device, config) = host_device_setup()?;
 
     let sample_rate = config.sample_rate();
     let stream = match config.sample_format() {
-        cpal::SampleFormat::I8 => make_stream::<i8>(&device, &config.into(), receiver),
-        cpal::SampleFormat::I16 => make_stream::<i16>(&device, &config.into(), receiver),
-        cpal::SampleFormat::I32 => make_stream::<i32>(&device, &config.into(), receiver),
-        cpal::SampleFormat::I64 => make_stream::<i64>(&device, &config.into(), receiver),
-        cpal::SampleFormat::U8 => make_stream::<u8>(&device, &config.into(), receiver),
-        cpal::SampleFormat::U16 => make_stream::<u16>(&device, &config.into(), receiver),
-        cpal::SampleFormat::U32 => make_stream::<u32>(&device, &config.into(), receiver),
-        cpal::SampleFormat::U64 => make_stream::<u64>(&device, &config.into(), receiver),
-        cpal::SampleFormat::F32 => make_stream::<f32>(&device, &config.into(), receiver),
-        cpal::SampleFormat::F64 => make_stream::<f64>(&device, &config.into(), receiver),
+        cpal::SampleFormat::I8 => make_stream::<i8, S>(&device, &config.into(), source),
+        cpal::SampleFormat::I16 => make_stream::<i16, S>(&device, &config.into(), source),
+        cpal::SampleFormat::I32 => make_stream::<i32, S>(&device, &config.into(), source),
+        cpal::SampleFormat::I64 => make_stream::<i64, S>(&device, &config.into(), source),
+        cpal::SampleFormat::U8 => make_stream::<u8, S>(&device, &config.into(), source),
+        cpal::SampleFormat::U16 => make_stream::<u16, S>(&device, &config.into(), source),
+        cpal::SampleFormat::U32 => make_stream::<u32, S>(&device, &config.into(), source),
+        cpal::SampleFormat::U64 => make_stream::<u64, S>(&device, &config.into(), source),
+        cpal::SampleFormat::F32 => make_stream::<f32, S>(&device, &config.into(), source),
+        cpal::SampleFormat::F64 => make_stream::<f64, S>(&device, &config.into(), source),
         sample_format => Err(anyhow::Error::msg(format!(
             "Unsupported sample format '{sample_format}'"
         ))),
@@ -40,25 +89,21 @@ fn host_device_setup() -> Result<(cpal::Device, cpal::SupportedStreamConfig), an
     Ok((device, config))
 }
 
-fn make_stream<T>(
+fn make_stream<T, S>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    receiver: Receiver<Vec<f32>>,
+    mut source: S,
 ) -> anyhow::Result<cpal::Stream>
 where
     T: cpal::SizedSample + cpal::FromSample<f32>,
+    S: SampleSource + Send + 'static,
 {
     // let sample_rate = config.sample_rate.0 as f32;
     let channels = config.channels as usize;
-    let mut synth = SynthReceiver {
-        receiver,
-        data: Vec::new(),
-        index: 0,
-    };
 
     let stream = device.build_output_stream(
         config,
-        move |data: &mut [T], _: &cpal::OutputCallbackInfo| write_data(data, channels, &mut synth),
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| write_data(data, channels, &mut source),
         |err| eprintln!("an error occurred on stream: {err}"),
         None,
     )?;
@@ -66,28 +111,66 @@ where
     Ok(stream)
 }
 
-fn write_data<T>(output: &mut [T], channels: usize, synth: &mut SynthReceiver)
+fn write_data<T, S>(output: &mut [T], channels: usize, source: &mut S)
 where
     T: cpal::Sample + cpal::FromSample<f32>,
+    S: SampleSource,
 {
     for frame in output.chunks_mut(channels) {
-        let value = synth.synth_sample();
+        let [left, right] = source.synth_frame();
 
-        let value: T = T::from_sample(value);
+        // mono devices get the average, extra channels the centre
+        let centre: T = T::from_sample((left + right) * 0.5);
         for sample in frame.iter_mut() {
-            *sample = value;
+            *sample = centre;
+        }
+        if channels >= 2 {
+            frame[0] = T::from_sample(left);
+            frame[1] = T::from_sample(right);
         }
     }
 }
 
-struct SynthReceiver {
-    receiver: Receiver<Vec<f32>>,
-    data: Vec<f32>,
+/// Something that produces one left and right frame per output frame.
+trait SampleSource {
+    fn synth_frame(&mut self) -> [f32; 2];
+}
+
+/// A rendered mono sample or stereo frame.
+trait Frame: Copy + Send + 'static {
+    fn stereo(self) -> [f32; 2];
+}
+
+impl Frame for f32 {
+    fn stereo(self) -> [f32; 2] {
+        [self, self]
+    }
+}
+
+impl Frame for [f32; 2] {
+    fn stereo(self) -> [f32; 2] {
+        self
+    }
+}
+
+struct SynthReceiver<F> {
+    receiver: Receiver<Vec<F>>,
+    data: Vec<F>,
     index: usize,
 }
 
-impl SynthReceiver {
-    pub fn synth_sample(&mut self) -> f32 {
+impl<F> SynthReceiver<F> {
+    fn new(receiver: Receiver<Vec<F>>) -> Self {
+        SynthReceiver {
+            receiver,
+            data: Vec::new(),
+            index: 0,
+        }
+    }
+}
+
+impl<F: Frame> SampleSource for SynthReceiver<F> {
+    fn synth_frame(&mut self) -> [f32; 2] {
         // receive new data if there is
         let result = self.receiver.try_recv();
         if let Ok(result) = result {
@@ -96,12 +179,40 @@ impl SynthReceiver {
         }
 
         // consume the data buffer
-        let mut value = 0.0;
+        let mut value = [0.0; 2];
         if self.index < self.data.len() {
-            value = self.data[self.index];
+            value = self.data[self.index].stereo();
             self.index += 1;
         }
 
         value
     }
 }
+
+struct LiveSynth {
+    receiver: Receiver<SynthCommand>,
+    synth: Synth,
+}
+
+impl SampleSource for LiveSynth {
+    fn synth_frame(&mut self) -> [f32; 2] {
+        // apply every pending command before producing the sample
+        while let Ok(command) = self.receiver.try_recv() {
+            match command {
+                SynthCommand::Play(params) => {
+                    self.synth.set_params(*params);
+                    self.synth.play_sample();
+                }
+                SynthCommand::Sources(sample, cycle) => {
+                    self.synth.set_sample(sample);
+                    self.synth.set_cycle(cycle);
+                }
+                SynthCommand::SetLive(param, value) => self.synth.set_live(param, value),
+                SynthCommand::Release => self.synth.release(),
+                SynthCommand::Stop => self.synth.stop(),
+            }
+        }
+
+        self.synth.synth_sample().unwrap_or(0.0).stereo()
+    }
+}
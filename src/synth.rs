@@ -1,16 +1,57 @@
+pub mod arpeggio;
+pub mod chip;
+pub mod cleanup;
+pub mod convolution;
+pub mod effects;
+pub mod envelope;
+pub mod evolve;
+pub mod fm;
+pub mod layered;
+pub mod lfo;
+pub mod live;
+pub mod looping;
 pub mod params;
+pub mod pitch;
 pub mod presets;
+pub mod sample;
 mod state;
+pub mod wavetable;
 
 use std::f32::consts::TAU;
+use std::ops::Range;
+use std::sync::Arc;
 
+use chip::{Channel, Chip};
+use effects::Effect;
+use live::LiveParam;
 use params::{SynthParams, WaveType};
+use sample::Sample;
 use state::SynthState;
+use wavetable::Cycle;
 
 use rand::prelude::*;
 
 const SUPERSAMPLING_FACTOR: usize = 8;
 
+/// Sample rate of the generated sound, in Hz.
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Fraction of the remaining distance a live parameter covers on each
+/// sample, about a 5ms glide that avoids zipper noise.
+const LIVE_SMOOTHING: f32 = 0.005;
+
+/// Pitch, duty cycle and volume of the voice on the latest sample,
+/// before the waveform is drawn.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Voice {
+    /// Pitch in Hz, slides, arpeggios and vibrato included.
+    pub hz: f32,
+    /// Fraction of the period the square wave stays high, 0..0.5.
+    pub duty: f32,
+    /// Envelope and tremolo level, above 1 during the punch.
+    pub volume: f32,
+}
+
 pub struct Synth {
     params: SynthParams,
     state: SynthState,
@@ -18,6 +59,25 @@ pub struct Synth {
     master_vol: f32,
     sound_vol: f32,
 
+    /// Targets of the live parameters still gliding, see `set_live`.
+    live_targets: [Option<f32>; LiveParam::ALL.len()],
+
+    /// Effects applied in order to the oscillator, see `effects`.
+    effects: Vec<Box<dyn Effect>>,
+    /// Effects applied in order after the envelope.
+    post_effects: Vec<Box<dyn Effect>>,
+
+    /// Recording played by the `Sample` wave type.
+    sample: Option<Arc<Sample>>,
+    /// Length in samples of the fades around repeat restarts, see
+    /// `set_repeat_fade`.
+    repeat_fade: usize,
+
+    /// Imported cycle played by the `Wavetable` wave type.
+    cycle: Option<Arc<Cycle>>,
+    /// Whether `cycle` is the full version of `params.wavetable`.
+    cycle_matches: bool,
+
     rng: StdRng,
 }
 
@@ -30,37 +90,174 @@ impl Synth {
             master_vol: 0.05,
             sound_vol: 0.5,
 
+            live_targets: Default::default(),
+
+            effects: effects::default_chain(),
+            post_effects: effects::default_post_chain(),
+
+            repeat_fade: 0,
+
+            sample: None,
+            cycle: None,
+            cycle_matches: false,
+
             rng: StdRng::from_os_rng(),
         }
     }
 
+    pub fn voice(&self) -> Voice {
+        self.state.voice
+    }
+
+    /// Whether the sound, or the tail of its post effects, still plays.
     pub fn is_playing(&self) -> bool {
-        self.state.playing_sample
+        self.state.playing_sample || self.state.tail_time > 0
     }
 
     pub fn set_params(&mut self, params: SynthParams) {
         self.params = params;
+        self.live_targets = Default::default();
+        self.match_cycle();
+    }
+
+    /// Fades the sound out over `seconds` before each repeat restart and
+    /// back in after it, so the jump in pitch doesn't click. Zero keeps
+    /// the hard restarts.
+    pub fn set_repeat_fade(&mut self, seconds: f32) {
+        self.repeat_fade = (seconds.max(0.0) * SAMPLE_RATE as f32) as usize;
+    }
+
+    /// Sets the recording played by the `Sample` wave type, which stays
+    /// silent without one.
+    pub fn set_sample(&mut self, sample: Option<Arc<Sample>>) {
+        self.sample = sample;
+    }
+
+    pub fn with_sample(mut self, sample: Arc<Sample>) -> Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    pub fn sample(&self) -> Option<&Arc<Sample>> {
+        self.sample.as_ref()
+    }
+
+    /// Sets an imported cycle with more points than a wavetable holds.
+    /// It plays in place of `SynthParams::wavetable` while that is the
+    /// reduced copy from `Cycle::wavetable`.
+    pub fn set_cycle(&mut self, cycle: Option<Arc<Cycle>>) {
+        self.cycle = cycle;
+        self.match_cycle();
+    }
+
+    pub fn with_cycle(mut self, cycle: Arc<Cycle>) -> Self {
+        self.set_cycle(Some(cycle));
+        self
+    }
+
+    pub fn cycle(&self) -> Option<&Arc<Cycle>> {
+        self.cycle.as_ref()
+    }
+
+    fn match_cycle(&mut self) {
+        self.cycle_matches = match (&self.cycle, &self.params.wavetable) {
+            (Some(cycle), Some(wavetable)) => cycle.wavetable() == *wavetable,
+            _ => false,
+        };
+    }
+
+    /// Replaces the effect chain. Pass `effects::default_chain()` with
+    /// extra effects to keep the classic filters and phaser.
+    pub fn with_effects(mut self, effects: Vec<Box<dyn Effect>>) -> Self {
+        self.effects = effects;
+        self
+    }
+
+    pub fn effects(&self) -> &[Box<dyn Effect>] {
+        &self.effects
+    }
+
+    /// The effect chain, in processing order. Changes take effect on the
+    /// next sample; added effects are reset when the next sound starts.
+    pub fn effects_mut(&mut self) -> &mut Vec<Box<dyn Effect>> {
+        &mut self.effects
+    }
+
+    /// Replaces the post effect chain, applied after the envelope at
+    /// `SAMPLE_RATE`. Pass `effects::default_post_chain()` with extra
+    /// effects to keep the effects set by the params.
+    pub fn with_post_effects(mut self, effects: Vec<Box<dyn Effect>>) -> Self {
+        self.post_effects = effects;
+        self
+    }
+
+    pub fn post_effects(&self) -> &[Box<dyn Effect>] {
+        &self.post_effects
+    }
+
+    /// The post effect chain, in processing order, see `effects_mut`.
+    pub fn post_effects_mut(&mut self) -> &mut Vec<Box<dyn Effect>> {
+        &mut self.post_effects
+    }
+
+    /// Changes a parameter of the playing sound without restarting it.
+    /// The value glides to the target over a few milliseconds. When no
+    /// sound is playing the value is applied right away.
+    pub fn set_live(&mut self, param: LiveParam, value: f32) {
+        if self.state.playing_sample {
+            self.live_targets[param as usize] = Some(value);
+        } else {
+            self.live_targets[param as usize] = None;
+            self.apply_live(param, value);
+        }
+    }
+
+    /// Current value of a live parameter.
+    pub fn live_value(&self, param: LiveParam) -> f32 {
+        match param {
+            LiveParam::BaseFreq => self.params.base_freq,
+            LiveParam::LpfFreq => self.params.lpf_freq,
+            LiveParam::HpfFreq => self.params.hpf_freq,
+            LiveParam::VibStrength => self.params.vib_strength,
+            LiveParam::VibSpeed => self.params.vib_speed,
+            LiveParam::Volume => self.sound_vol,
+        }
     }
 
     pub fn play_sample(&mut self) {
+        // settle pending live changes so the new sound starts from them
+        for param in LiveParam::ALL {
+            if let Some(value) = self.live_targets[param as usize].take() {
+                self.apply_live(param, value);
+            }
+        }
+
         self.reset_sample(false);
         self.state.playing_sample = true;
     }
 
     pub fn synth_sample(&mut self) -> Option<f32> {
         if !self.state.playing_sample {
-            return None;
+            return self.synth_tail();
         }
 
+        self.step_live();
+
         self.state.rep_time += 1;
         if self.state.rep_limit != 0 && self.state.rep_time >= self.state.rep_limit {
             self.state.rep_time = 0;
             self.reset_sample(true);
+            self.state.repeated = true;
         }
 
         // frequency envelopes/arpeggios
         self.state.arp_time += 1;
-        if self.state.arp_limit != 0 && self.state.arp_time >= self.state.arp_limit {
+        if let Some(arpeggio) = &self.params.arpeggio {
+            if self.state.arp_time >= arpeggio.step_length() {
+                self.state.arp_time = 0;
+                self.state.arp_step = arpeggio.next_step(self.state.arp_step);
+            }
+        } else if self.state.arp_limit != 0 && self.state.arp_time >= self.state.arp_limit {
             self.state.arp_limit = 0;
             self.state.fperiod *= self.state.arp_mod;
         }
@@ -74,11 +271,52 @@ impl Synth {
                 self.state.playing_sample = false;
             }
         }
+        let mut modulation = lfo::step(&self.params.lfos, &mut self.state.lfos, &mut self.rng);
+
+        if let Some(arpeggio) = &self.params.arpeggio {
+            modulation.period *= arpeggio.period_ratio(self.state.arp_step);
+        }
+
+        // custom envelopes
+        self.state.env_clock += 1;
+        let env_seconds = self.state.env_clock as f32 / SAMPLE_RATE as f32;
+        if let Some(envelope) = &self.params.pitch_env {
+            modulation.period *= (-2.0 * envelope.level(env_seconds)).exp2();
+        }
+        if let Some(envelope) = &self.params.filter_env {
+            modulation.lpf *= (4.0 * envelope.level(env_seconds)).exp2();
+        }
+
         let mut rfperiod = self.state.fperiod;
         if self.state.vib_amp > 0.0 {
             self.state.vib_phase += self.state.vib_speed;
             rfperiod = self.state.fperiod * (1.0 + self.state.vib_phase.sin() * self.state.vib_amp);
         }
+        rfperiod *= modulation.period;
+
+        // the chip timers only reach some pitches
+        let chip = self
+            .params
+            .chip
+            .map(|chip| (chip, chip.channel(self.params.wave_type)));
+        if let Some((chip, channel)) = chip {
+            rfperiod = chip.quantize_period(channel, rfperiod);
+        }
+        let lfsr_clocks = match chip {
+            Some((chip, Channel::Noise)) => {
+                let rate = (SAMPLE_RATE as usize * SUPERSAMPLING_FACTOR) as f32;
+                chip.noise_rate(32.0 * rate / rfperiod) / rate
+            }
+            _ => 0.0,
+        };
+
+        // recorded samples play at their own speed at the root pitch
+        let sample_step = match &self.sample {
+            Some(sample) => {
+                sample.sample_rate() as f64 / (self.params.sample.root as f64 * rfperiod as f64)
+            }
+            None => 0.0,
+        };
 
         self.state.period = rfperiod as i32;
         if self.state.period < 8 {
@@ -86,48 +324,74 @@ impl Synth {
         }
         self.state.square_duty += self.state.square_slide;
         self.state.square_duty = self.state.square_duty.clamp(0.0, 0.5);
+        let square_duty = (self.state.square_duty + modulation.duty).clamp(0.0, 0.5);
 
         // volume envelope
-        self.state.env_time += 1;
-        if self.state.env_time > self.state.env_length[self.state.env_stage as usize] {
-            self.state.env_time = 0;
-            self.state.env_stage += 1;
-            if self.state.env_stage == 3 {
+        if let Some(envelope) = &self.params.volume_env {
+            // held sounds stay on the sustain point until release() is called
+            let holding = self.params.env_hold && !self.state.released;
+            let sustain = (envelope.sustain_time() * SAMPLE_RATE as f32) as usize;
+            if !holding || self.state.volume_clock < sustain {
+                self.state.volume_clock += 1;
+            }
+            let seconds = self.state.volume_clock as f32 / SAMPLE_RATE as f32;
+            if seconds > envelope.duration() {
                 self.state.playing_sample = false;
             }
-        }
-
-        self.state.env_vol = match self.state.env_stage {
-            0 => self.state.env_time as f32 / self.state.env_length[0] as f32,
-            1 => {
-                1.0 + (1.0 - self.state.env_time as f32 / self.state.env_length[1] as f32)
-                    * 2.0
-                    * self.params.env_punch
+            self.state.env_vol = envelope.level(seconds);
+        } else {
+            self.state.env_time += 1;
+            if self.state.env_time > self.state.env_length[self.state.env_stage as usize] {
+                if self.state.env_stage == 1 && self.params.env_hold && !self.state.released {
+                    // hold the end of the sustain until release() is called
+                    self.state.env_time = self.state.env_length[1];
+                } else {
+                    self.state.env_time = 0;
+                    self.state.env_stage += 1;
+                    if self.state.env_stage == 3 {
+                        self.state.playing_sample = false;
+                    }
+                }
             }
-            2 => 1.0 - self.state.env_time as f32 / self.state.env_length[2] as f32,
-            _ => self.state.env_vol,
-        };
 
-        if self.state.env_vol.is_infinite() {
-            self.state.env_vol = 1.0;
+            self.state.env_vol = match self.state.env_stage {
+                0 => self.state.env_time as f32 / self.state.env_length[0] as f32,
+                1 => {
+                    1.0 + (1.0 - self.state.env_time as f32 / self.state.env_length[1] as f32)
+                        * 2.0
+                        * self.params.env_punch
+                }
+                2 => {
+                    self.state.release_vol
+                        * (1.0 - self.state.env_time as f32 / self.state.env_length[2] as f32)
+                }
+                _ => self.state.env_vol,
+            };
         }
 
-        // phaser step
-        self.state.fphase += self.state.fdphase;
-        self.state.iphase = (self.state.fphase as i32).abs();
-        if self.state.iphase > 1023 {
-            self.state.iphase = 1023
+        if self.state.env_vol.is_infinite() {
+            self.state.env_vol = 1.0;
         }
 
-        if self.state.flthp_d != 0.0 {
-            self.state.flthp *= self.state.flthp_d;
+        self.state.voice = Voice {
+            hz: (SAMPLE_RATE as usize * SUPERSAMPLING_FACTOR) as f32 / rfperiod,
+            duty: square_duty,
+            volume: self.state.env_vol * modulation.volume,
+        };
 
-            self.state.flthp = self.state.flthp.clamp(0.00001, 0.1);
+        if chip.is_none() {
+            for effect in &mut self.effects {
+                effect.control(&modulation);
+            }
         }
 
-        let mut ssample: f32 = 0.0;
+        self.state.fm_index = (self.state.fm_index
+            + self.params.fm.index_sweep / SAMPLE_RATE as f32)
+            .clamp(0.0, fm::MAX_INDEX);
+
+        let mut block = [0.0; SUPERSAMPLING_FACTOR];
         // 8x supersampling
-        for _si in 0..SUPERSAMPLING_FACTOR {
+        for sample in &mut block {
             self.state.phase += 1;
 
             if self.state.phase >= self.state.period {
@@ -141,10 +405,14 @@ impl Synth {
 
             // base waveform
             let fp = self.state.phase as f32 / self.state.period as f32;
-            let mut sample = match self.params.wave_type {
+            if let Some((_, channel)) = chip {
+                *sample = self.chip_sample(channel, fp, square_duty, lfsr_clocks);
+                continue;
+            }
+            *sample = match self.params.wave_type {
                 WaveType::Sine => (fp * TAU).sin(),
                 WaveType::Square => {
-                    if fp < self.state.square_duty {
+                    if fp < square_duty {
                         0.5
                     } else {
                         -0.5
@@ -155,47 +423,160 @@ impl Synth {
                     let index = self.state.phase * 32 / self.state.period;
                     self.state.noise_buffer[index as usize]
                 }
+                WaveType::Sample => match &self.sample {
+                    Some(sample) => {
+                        let value = sample.read(self.state.sample_pos, self.params.sample.mode);
+                        self.state.sample_pos += sample_step;
+                        value
+                    }
+                    None => 0.0,
+                },
+                WaveType::Wavetable => match (&self.cycle, &self.params.wavetable) {
+                    (Some(cycle), _) if self.cycle_matches => cycle.sample(fp),
+                    (_, Some(wavetable)) => wavetable.sample(fp),
+                    (_, None) => (fp * TAU).sin(),
+                },
+                WaveType::Fm => {
+                    self.state.fm_phase = (self.state.fm_phase
+                        + self.params.fm.ratio / self.state.period as f32)
+                        .fract();
+                    let modulator = (self.state.fm_phase * TAU).sin();
+                    (fp * TAU + self.state.fm_index * modulator).sin()
+                }
             };
 
-            // lp filter
-            let pp = self.state.fltp;
-            self.state.fltw *= self.state.fltw_d;
-            self.state.fltw = self.state.fltw.clamp(0.0, 0.1);
-
-            if self.params.lpf_freq != 1.0 {
-                self.state.fltdp += (sample - self.state.fltp) * self.state.fltw;
-                self.state.fltdp -= self.state.fltdp * self.state.fltdmp;
-            } else {
-                self.state.fltp = sample;
-                self.state.fltdp = 0.0;
+            if let Some(ring_mod) = &self.params.ring_mod {
+                self.state.ring_phase =
+                    (self.state.ring_phase + ring_mod.ratio / self.state.period as f32).fract();
+                let carrier = (self.state.ring_phase * TAU).sin();
+                *sample *= 1.0 - ring_mod.mix + ring_mod.mix * carrier;
             }
-            self.state.fltp += self.state.fltdp;
+        }
 
-            // hp filter
-            self.state.fltphp += self.state.fltp - pp;
-            self.state.fltphp -= self.state.fltphp * self.state.flthp;
-            sample = self.state.fltphp;
+        if chip.is_none() {
+            for effect in &mut self.effects {
+                effect.process_block(&mut block);
+            }
+        }
 
-            // phaser
-            let index = (self.state.ipp & 1023) as usize;
-            self.state.phaser_buffer[index] = sample;
+        // the chip volume registers only hold a few levels
+        let (env_vol, lfo_volume) = match chip {
+            Some((_, channel)) => (
+                Chip::quantize_volume(channel, self.state.env_vol * modulation.volume),
+                1.0,
+            ),
+            None => (self.state.env_vol, modulation.volume),
+        };
 
-            let index = ((self.state.ipp - self.state.iphase + 1024) & 1023) as usize;
-            sample += self.state.phaser_buffer[index];
+        // envelop application
+        let mut ssample: f32 = 0.0;
+        for sample in block {
+            ssample += sample * env_vol;
+        }
 
-            self.state.ipp = (self.state.ipp + 1) & 1023;
+        ssample /= SUPERSAMPLING_FACTOR as f32;
+        if chip.is_none() {
+            for effect in &mut self.post_effects {
+                effect.control(&modulation);
+                ssample = effect.process(ssample);
+            }
+        }
 
-            // envelop application
-            ssample += sample * self.state.env_vol;
+        // fade out before each repeat restart and back in after it, the
+        // chips restart hard
+        if self.repeat_fade > 0 && self.state.rep_limit > 0 && chip.is_none() {
+            let fade = self
+                .repeat_fade
+                .min(self.state.rep_limit as usize / 2)
+                .max(1) as f32;
+            let to_restart = (self.state.rep_limit - self.state.rep_time) as f32;
+            let from_restart = if self.state.repeated {
+                self.state.rep_time as f32
+            } else {
+                fade
+            };
+            ssample *= (to_restart.min(from_restart) / fade).min(1.0);
         }
 
-        ssample = ssample / SUPERSAMPLING_FACTOR as f32 * self.master_vol;
-        ssample *= 2.0 * self.sound_vol;
+        ssample *= self.master_vol;
+        ssample *= 2.0 * self.sound_vol * lfo_volume;
         ssample = ssample.clamp(-1.0, 1.0);
 
         Some(ssample)
     }
 
+    /// Silences the sound immediately, tails included.
+    pub fn stop(&mut self) {
+        self.state.playing_sample = false;
+        self.state.tail_time = 0;
+    }
+
+    /// Lets the post effects ring out once the sound has ended.
+    fn synth_tail(&mut self) -> Option<f32> {
+        if self.state.tail_time == 0 {
+            return None;
+        }
+        self.state.tail_time -= 1;
+
+        let mut ssample = 0.0;
+        for effect in &mut self.post_effects {
+            ssample = effect.process(ssample);
+        }
+
+        ssample *= self.master_vol;
+        ssample *= 2.0 * self.sound_vol;
+        Some(ssample.clamp(-1.0, 1.0))
+    }
+
+    /// Ends the sustain stage and lets the envelope decay from its
+    /// current volume. Does nothing once the sound is already decaying.
+    /// Custom volume envelopes move on from their sustain point into
+    /// their last segment.
+    pub fn release(&mut self) {
+        self.state.released = true;
+        if self.state.playing_sample && self.state.env_stage < 2 {
+            self.state.release_vol = self.state.env_vol;
+            self.state.env_stage = 2;
+            self.state.env_time = 0;
+        }
+    }
+
+    /// Range of rendered samples covered by the sustain stage.
+    pub fn sustain_range(&self) -> Range<usize> {
+        let start = envelope_length(self.params.env_attack) as usize;
+        start..start + envelope_length(self.params.env_sustain) as usize + 1
+    }
+
+    /// Plays the sound from the start and collects it into a buffer.
+    ///
+    /// Held sounds are released where their sustain would normally end.
+    pub fn render(&mut self) -> Vec<f32> {
+        let sustain_end = match &self.params.volume_env {
+            Some(envelope) => (envelope.sustain_time() * SAMPLE_RATE as f32) as usize + 1,
+            None => {
+                (envelope_length(self.params.env_attack)
+                    + envelope_length(self.params.env_sustain)
+                    + 2) as usize
+            }
+        };
+        self.render_held(sustain_end)
+    }
+
+    /// Plays the sound from the start, releases it after `hold` samples
+    /// and collects it into a buffer.
+    pub fn render_held(&mut self, hold: usize) -> Vec<f32> {
+        self.play_sample();
+
+        let mut data = Vec::new();
+        while let Some(value) = self.synth_sample() {
+            data.push(value);
+            if data.len() >= hold {
+                self.release();
+            }
+        }
+        data
+    }
+
     pub fn synth_sample_buffer(&mut self, length: usize, buffer: &mut [f32]) {
         for item in buffer.iter_mut().take(length) {
             match self.synth_sample() {
@@ -205,15 +586,89 @@ impl Synth {
         }
     }
 
+    /// Moves every gliding live parameter one sample closer to its target.
+    fn step_live(&mut self) {
+        for param in LiveParam::ALL {
+            let Some(target) = self.live_targets[param as usize] else {
+                continue;
+            };
+
+            let current = self.live_value(param);
+            let mut value = current + (target - current) * LIVE_SMOOTHING;
+            if (target - value).abs() < 0.00001 {
+                value = target;
+                self.live_targets[param as usize] = None;
+            }
+            self.apply_live(param, value);
+        }
+    }
+
+    /// Sets a live parameter and updates the playing state derived from
+    /// it, keeping any sweep in progress.
+    fn apply_live(&mut self, param: LiveParam, value: f32) {
+        let current = self.live_value(param);
+        match param {
+            LiveParam::BaseFreq => {
+                self.state.fperiod *= pitch::period(value) / pitch::period(current);
+                self.params.base_freq = value;
+            }
+            LiveParam::LpfFreq | LiveParam::HpfFreq => {
+                for effect in &mut self.effects {
+                    effect.set_live(param, current, value, &self.params);
+                }
+                match param {
+                    LiveParam::LpfFreq => self.params.lpf_freq = value,
+                    _ => self.params.hpf_freq = value,
+                }
+            }
+            LiveParam::VibStrength => {
+                self.state.vib_amp = value * 0.5;
+                self.params.vib_strength = value;
+            }
+            LiveParam::VibSpeed => {
+                self.state.vib_speed = value.powf(2.0) * 0.01;
+                self.params.vib_speed = value;
+            }
+            LiveParam::Volume => self.sound_vol = value,
+        }
+    }
+
+    /// Waveform of a chip channel at `phase`, 0..1 covering one period.
+    fn chip_sample(&mut self, channel: Channel, phase: f32, duty: f32, lfsr_clocks: f32) -> f32 {
+        match channel {
+            Channel::Pulse => {
+                if phase < Chip::quantize_duty(duty) {
+                    0.5
+                } else {
+                    -0.5
+                }
+            }
+            Channel::Triangle => Chip::triangle(phase),
+            Channel::Wave => Chip::wave(&self.params, phase),
+            Channel::Noise => {
+                self.state.lfsr_clock += lfsr_clocks;
+                while self.state.lfsr_clock >= 1.0 {
+                    self.state.lfsr_clock -= 1.0;
+                    self.state.lfsr = chip::lfsr_step(self.state.lfsr);
+                }
+                if self.state.lfsr & 1 == 0 { 0.5 } else { -0.5 }
+            }
+        }
+    }
+
     fn reset_sample(&mut self, restart: bool) {
         if !restart {
             self.state.phase = 0;
+            self.state.fm_phase = 0.0;
+            self.state.ring_phase = 0.0;
+            self.state.lfsr = 1;
+            self.state.lfsr_clock = 0.0;
         }
 
-        self.state.fperiod = 100.0 / (self.params.base_freq.powf(2.0) + 0.001);
+        self.state.fperiod = pitch::period(self.params.base_freq);
         self.state.period = self.state.fperiod as i32;
 
-        self.state.fmaxperiod = 100.0 / (self.params.freq_limit.powf(2.0) + 0.001);
+        self.state.fmaxperiod = pitch::period(self.params.freq_limit);
         self.state.fslide = 1.0 - self.params.freq_ramp.powf(3.0) * 0.01;
         self.state.fdslide = -self.params.freq_dramp.powf(3.0) * 0.000001;
 
@@ -227,25 +682,27 @@ impl Synth {
         }
 
         self.state.arp_time = 0;
+        self.state.arp_step = 0;
+        self.state.sample_pos = 0.0;
+        self.state.fm_index = self.params.fm.index;
         self.state.arp_limit = ((1.0 - self.params.arp_speed).powf(2.0) * 20_000.0 + 32.0) as i32;
         if self.params.arp_speed == 1.0 {
             self.state.arp_limit = 0;
         }
 
         if !restart {
-            self.state.fltp = 0.0;
-            self.state.fltdp = 0.0;
-            self.state.fltw = self.params.lpf_freq.powf(3.0) * 0.1;
-            self.state.fltw_d = 1.0 + self.params.lpf_ramp * 0.0001;
-            self.state.fltdmp =
-                5.0 / (1.0 + self.params.lpf_resonance.powf(2.0) * 20.0) * (0.01 + self.state.fltw);
-            if self.state.fltdmp > 0.8 {
-                self.state.fltdmp = 0.8;
+            let sample_rate = (SAMPLE_RATE as usize * SUPERSAMPLING_FACTOR) as f32;
+            for effect in &mut self.effects {
+                effect.reset(&self.params, sample_rate);
             }
-
-            self.state.fltphp = 0.0;
-            self.state.flthp = self.params.hpf_freq.powf(2.0) * 0.1;
-            self.state.flthp_d = 1.0 + self.params.hpf_ramp * 0.0003;
+            for effect in &mut self.post_effects {
+                effect.reset(&self.params, SAMPLE_RATE as f32);
+            }
+            self.state.repeated = false;
+            self.state.tail_time = match self.params.chip {
+                Some(_) => 0,
+                None => self.post_effects.iter().map(|effect| effect.tail()).sum(),
+            };
 
             // reset vibrato
             self.state.vib_phase = 0.0;
@@ -253,25 +710,18 @@ impl Synth {
             self.state.vib_amp = self.params.vib_strength * 0.5;
 
             // reset envelope
+            self.state.env_clock = 0;
+            self.state.volume_clock = 0;
             self.state.env_vol = 0.0;
             self.state.env_stage = 0;
             self.state.env_time = 0;
-            self.state.env_length[0] = (self.params.env_attack.powf(2.0) * 100000.0) as i32;
-            self.state.env_length[1] = (self.params.env_sustain.powf(2.0) * 100000.0) as i32;
-            self.state.env_length[2] = (self.params.env_decay.powf(2.0) * 100000.0) as i32;
+            self.state.env_length[0] = envelope_length(self.params.env_attack);
+            self.state.env_length[1] = envelope_length(self.params.env_sustain);
+            self.state.env_length[2] = envelope_length(self.params.env_decay);
+            self.state.released = false;
+            self.state.release_vol = 1.0;
 
-            self.state.fphase = self.params.pha_offset.powf(2.0) * 1020.0;
-            if self.params.pha_offset < 0.0 {
-                self.state.fphase = -self.state.fphase;
-            }
-            self.state.fdphase = self.params.pha_ramp.powf(2.0) * 1.0;
-            if self.params.pha_ramp < 0.0 {
-                self.state.fdphase = -self.state.fdphase;
-            }
-            self.state.iphase = self.state.fphase.abs() as i32;
-            self.state.ipp = 0;
-
-            self.state.phaser_buffer.fill(0.0);
+            self.state.lfos = Default::default();
 
             for item in &mut self.state.noise_buffer {
                 *item = self.rng.random::<f32>() * 2.0 - 1.0;
@@ -286,3 +736,55 @@ impl Synth {
         }
     }
 }
+
+/// Renders `steps` sounds evenly spaced between `a` and `b`.
+pub fn render_morph(a: &SynthParams, b: &SynthParams, steps: usize) -> Vec<Vec<f32>> {
+    let mut synth = Synth::new(*a);
+    SynthParams::morph(a, b, steps)
+        .into_iter()
+        .map(|params| {
+            synth.set_params(params);
+            synth.render()
+        })
+        .collect()
+}
+
+/// Converts an envelope time parameter into a length in samples.
+fn envelope_length(value: f32) -> i32 {
+    (value.powf(2.0) * 100000.0) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use envelope::{Breakpoint, Envelope};
+
+    #[test]
+    fn held_volume_envelopes_wait_for_release() {
+        let envelope = Envelope::new(&[
+            Breakpoint::new(0.0, 0.0, 0.0),
+            Breakpoint::new(0.01, 1.0, 0.0),
+            Breakpoint::new(0.05, 0.5, 0.0),
+            Breakpoint::new(0.1, 0.0, 0.0),
+        ]);
+        let params = SynthParams {
+            volume_env: Some(envelope),
+            ..Default::default()
+        };
+        let seconds = |data: Vec<f32>| data.len() as f32 / SAMPLE_RATE as f32;
+
+        let played = seconds(Synth::new(params).render_held(SAMPLE_RATE as usize));
+        assert!((played - 0.1).abs() < 0.01, "{played}");
+
+        let params = SynthParams {
+            env_hold: true,
+            ..params
+        };
+        let held = seconds(Synth::new(params).render_held(SAMPLE_RATE as usize));
+        assert!((held - 1.05).abs() < 0.01, "{held}");
+
+        // released where the envelope reaches its sustain point
+        let rendered = seconds(Synth::new(params).render());
+        assert!((rendered - 0.1).abs() < 0.01, "{rendered}");
+    }
+}
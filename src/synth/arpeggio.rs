@@ -0,0 +1,115 @@
+//! Arpeggios as patterns of semitone offsets.
+//!
+//! The legacy `arp_mod`/`arp_speed` pair makes a single pitch jump. An
+//! `Arpeggio` instead walks through up to `MAX_ARP_STEPS` offsets from
+//! the base pitch, holding each one for the same time. The pattern
+//! restarts whenever the sound repeats.
+
+use serde::{Deserialize, Serialize};
+
+use super::SAMPLE_RATE;
+
+/// Most steps an arpeggio can hold.
+pub const MAX_ARP_STEPS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ArpeggioData", into = "ArpeggioData")]
+pub struct Arpeggio {
+    steps: [f32; MAX_ARP_STEPS],
+    len: usize,
+    /// Time spent on each step, in seconds.
+    pub step_time: f32,
+    /// Whether the pattern starts over after the last step instead of
+    /// holding it.
+    pub looping: bool,
+}
+
+impl Default for Arpeggio {
+    fn default() -> Self {
+        Arpeggio::new(&[0.0, 4.0, 7.0, 12.0], 0.05, false)
+    }
+}
+
+impl Arpeggio {
+    /// Creates an arpeggio from up to `MAX_ARP_STEPS` offsets in
+    /// semitones.
+    pub const fn new(steps: &[f32], step_time: f32, looping: bool) -> Self {
+        let mut arpeggio = Arpeggio {
+            steps: [0.0; MAX_ARP_STEPS],
+            len: 0,
+            step_time,
+            looping,
+        };
+        arpeggio.set_steps(steps);
+        arpeggio
+    }
+
+    pub fn steps(&self) -> &[f32] {
+        &self.steps[..self.len]
+    }
+
+    /// Replaces the pattern, keeping at most `MAX_ARP_STEPS` offsets.
+    /// An empty pattern becomes a single step on the base pitch.
+    pub const fn set_steps(&mut self, steps: &[f32]) {
+        let steps = if steps.is_empty() { &[0.0] } else { steps };
+        self.len = if steps.len() < MAX_ARP_STEPS {
+            steps.len()
+        } else {
+            MAX_ARP_STEPS
+        };
+        let (steps, _) = steps.split_at(self.len);
+        let (target, _) = self.steps.split_at_mut(self.len);
+        target.copy_from_slice(steps);
+    }
+
+    /// Length of a step in samples.
+    pub fn step_length(&self) -> i32 {
+        ((self.step_time * SAMPLE_RATE as f32) as i32).max(1)
+    }
+
+    /// Step following `step`, one-shot patterns stay on the last one.
+    pub fn next_step(&self, step: usize) -> usize {
+        if step + 1 < self.len {
+            step + 1
+        } else if self.looping {
+            0
+        } else {
+            step
+        }
+    }
+
+    /// Multiplier of the oscillator period while on `step`.
+    pub fn period_ratio(&self, step: usize) -> f32 {
+        (-self.steps[step.min(self.len - 1)] / 12.0).exp2()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ArpeggioData {
+    steps: Vec<f32>,
+    step_time: f32,
+    looping: bool,
+}
+
+impl Default for ArpeggioData {
+    fn default() -> Self {
+        Arpeggio::default().into()
+    }
+}
+
+impl From<ArpeggioData> for Arpeggio {
+    fn from(data: ArpeggioData) -> Self {
+        Arpeggio::new(&data.steps, data.step_time, data.looping)
+    }
+}
+
+impl From<Arpeggio> for ArpeggioData {
+    fn from(arpeggio: Arpeggio) -> Self {
+        ArpeggioData {
+            steps: arpeggio.steps().to_vec(),
+            step_time: arpeggio.step_time,
+            looping: arpeggio.looping,
+        }
+    }
+}
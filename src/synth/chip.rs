@@ -0,0 +1,347 @@
+//! Render modes restricted to what a sound chip can play.
+//!
+//! With `SynthParams::chip` set, every wave type is played by the
+//! nearest channel of the chip: pulse waves with four duty cycles,
+//! stepped triangle or wave RAM, and LFSR noise clocked from the
+//! hardware rate table. Pitches snap to the chip timers, volumes to
+//! its 4-bit levels, and the filters, phaser, ring modulation and post
+//! effects, which the chip doesn't have, are skipped.
+
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+use super::lfo::LfoTarget;
+use super::params::{SynthParams, WaveType};
+use super::wavetable::Wavetable;
+use super::{SAMPLE_RATE, SUPERSAMPLING_FACTOR};
+
+/// CPU clock of the NTSC NES, in Hz.
+const NES_CLOCK: f32 = 1_789_773.0;
+/// Noise timer periods of the NTSC 2A03, in CPU cycles.
+const NES_NOISE_PERIODS: [f32; 16] = [
+    4.0, 8.0, 16.0, 32.0, 64.0, 96.0, 128.0, 160.0, 202.0, 254.0, 380.0, 508.0, 762.0, 1016.0,
+    2034.0, 4068.0,
+];
+/// Levels of the NES triangle sequencer, 32 steps per period.
+const NES_TRIANGLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+/// Base clock of the Game Boy pulse channel timers, in Hz.
+const GB_PULSE_CLOCK: f32 = 131_072.0;
+/// Base clock of the Game Boy wave channel timer, in Hz.
+const GB_WAVE_CLOCK: f32 = 65_536.0;
+/// Base clock of the Game Boy noise channel, in Hz.
+const GB_NOISE_CLOCK: f32 = 524_288.0;
+/// Samples in the Game Boy wave RAM.
+pub const GB_WAVE_POINTS: usize = 32;
+/// Output levels of the Game Boy wave channel.
+const GB_WAVE_LEVELS: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+
+/// Fraction of the period the pulse channels stay high. 75% sounds
+/// like 25% inverted, so the synth plays it as 25%.
+const DUTIES: [f32; 3] = [0.125, 0.25, 0.5];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Chip {
+    /// The 2A03 of the NES: two pulses, a triangle and noise.
+    Nes,
+    /// The DMG Game Boy: two pulses, a 32-step wave channel and noise.
+    GameBoy,
+}
+
+impl TryFrom<&str> for Chip {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "nes" => Ok(Self::Nes),
+            "2a03" => Ok(Self::Nes),
+            "gb" => Ok(Self::GameBoy),
+            "gameboy" => Ok(Self::GameBoy),
+            "dmg" => Ok(Self::GameBoy),
+            _ => Err(format!("Unknown chip: {}", value)),
+        }
+    }
+}
+
+/// Chip channel playing a wave type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Pulse,
+    /// The NES triangle, 16 levels without volume control.
+    Triangle,
+    /// The Game Boy wave RAM, 32 4-bit samples at 4 volume levels.
+    Wave,
+    Noise,
+}
+
+impl Chip {
+    pub const ALL: [Chip; 2] = [Chip::Nes, Chip::GameBoy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Chip::Nes => "NES",
+            Chip::GameBoy => "Game Boy",
+        }
+    }
+
+    /// Channel that plays `wave_type` on this chip.
+    pub fn channel(self, wave_type: WaveType) -> Channel {
+        match (self, wave_type) {
+            (_, WaveType::Noise) => Channel::Noise,
+            (Chip::Nes, WaveType::Sine | WaveType::Wavetable | WaveType::Fm) => Channel::Triangle,
+            (
+                Chip::GameBoy,
+                WaveType::Sine | WaveType::Sawtooth | WaveType::Wavetable | WaveType::Fm,
+            ) => Channel::Wave,
+            _ => Channel::Pulse,
+        }
+    }
+
+    /// Timer register value of the channel closest to `hz`.
+    pub fn timer(self, channel: Channel, hz: f32) -> u16 {
+        let timer = match (self, channel) {
+            (Chip::Nes, Channel::Triangle) => (NES_CLOCK / (32.0 * hz) - 1.0).clamp(2.0, 2047.0),
+            // timers below 8 silence the pulse channels
+            (Chip::Nes, _) => (NES_CLOCK / (16.0 * hz) - 1.0).clamp(8.0, 2047.0),
+            (Chip::GameBoy, Channel::Wave) => (2048.0 - GB_WAVE_CLOCK / hz).clamp(0.0, 2047.0),
+            (Chip::GameBoy, _) => (2048.0 - GB_PULSE_CLOCK / hz).clamp(0.0, 2047.0),
+        };
+        timer.round() as u16
+    }
+
+    /// Pitch in Hz the channel plays with a timer register value.
+    pub fn timer_hz(self, channel: Channel, timer: u16) -> f32 {
+        let timer = timer as f32;
+        match (self, channel) {
+            (Chip::Nes, Channel::Triangle) => NES_CLOCK / (32.0 * (timer + 1.0)),
+            (Chip::Nes, _) => NES_CLOCK / (16.0 * (timer + 1.0)),
+            (Chip::GameBoy, Channel::Wave) => GB_WAVE_CLOCK / (2048.0 - timer),
+            (Chip::GameBoy, _) => GB_PULSE_CLOCK / (2048.0 - timer),
+        }
+    }
+
+    /// Nearest pitch the channel timer can produce, `period` being in
+    /// supersampled steps like the synth period.
+    pub fn quantize_period(self, channel: Channel, period: f32) -> f32 {
+        if channel == Channel::Noise {
+            return period;
+        }
+
+        let rate = (SAMPLE_RATE as usize * SUPERSAMPLING_FACTOR) as f32;
+        let timer = self.timer(channel, rate / period.max(1.0));
+        rate / self.timer_hz(channel, timer)
+    }
+
+    /// Nearest clock rate of the noise LFSR to `rate`, in Hz.
+    pub fn noise_rate(self, rate: f32) -> f32 {
+        self.nearest_noise(rate).1
+    }
+
+    /// Noise register value selecting the LFSR clock nearest to `rate`:
+    /// the period index on the NES, the clock shift and divisor on the
+    /// Game Boy.
+    pub fn noise_register(self, rate: f32) -> u8 {
+        self.nearest_noise(rate).0
+    }
+
+    fn nearest_noise(self, rate: f32) -> (u8, f32) {
+        let rates: Vec<(u8, f32)> = match self {
+            Chip::Nes => (0..)
+                .zip(NES_NOISE_PERIODS)
+                .map(|(index, period)| (index, NES_CLOCK / period))
+                .collect(),
+            Chip::GameBoy => (0..=13u8)
+                .flat_map(|shift| {
+                    (0..8u8).map(move |divisor| {
+                        let clock = GB_NOISE_CLOCK / (2 << shift) as f32;
+                        let clock = match divisor {
+                            0 => clock * 2.0,
+                            _ => clock / divisor as f32,
+                        };
+                        (shift << 4 | divisor, clock)
+                    })
+                })
+                .collect(),
+        };
+        let distance = |candidate: f32| (candidate / rate).ln().abs();
+        rates
+            .into_iter()
+            .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+            .unwrap_or((0, rate))
+    }
+
+    /// Nearest duty cycle of the pulse channels.
+    pub fn quantize_duty(duty: f32) -> f32 {
+        DUTIES
+            .into_iter()
+            .min_by(|a, b| (a - duty).abs().total_cmp(&(b - duty).abs()))
+            .unwrap_or(0.5)
+    }
+
+    /// Nearest level the channel can play of a 0..1 volume.
+    pub fn quantize_volume(channel: Channel, volume: f32) -> f32 {
+        let volume = volume.clamp(0.0, 1.0);
+        match channel {
+            Channel::Pulse | Channel::Noise => (volume * 15.0).round() / 15.0,
+            Channel::Triangle => {
+                if volume > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Channel::Wave => GB_WAVE_LEVELS
+                .into_iter()
+                .min_by(|a, b| (a - volume).abs().total_cmp(&(b - volume).abs()))
+                .unwrap_or(1.0),
+        }
+    }
+
+    /// Value of the NES triangle at `phase`, 0..1 covering one period.
+    pub fn triangle(phase: f32) -> f32 {
+        let step = ((phase * 32.0) as usize).min(31);
+        NES_TRIANGLE[step] as f32 / 7.5 - 1.0
+    }
+
+    /// Nearest 4-bit level of the Game Boy wave RAM to a -1..1 value.
+    pub fn quantize_wave(value: f32) -> f32 {
+        ((value.clamp(-1.0, 1.0) + 1.0) * 7.5).round() / 7.5 - 1.0
+    }
+
+    /// Reduces a waveform to the 32 4-bit samples of the Game Boy wave
+    /// RAM.
+    pub fn wave_ram(waveform: impl Fn(f32) -> f32) -> [f32; GB_WAVE_POINTS] {
+        std::array::from_fn(|i| Chip::quantize_wave(waveform(i as f32 / GB_WAVE_POINTS as f32)))
+    }
+
+    /// Value of the Game Boy wave channel at `phase` for the wave type
+    /// of `params`.
+    pub fn wave(params: &SynthParams, phase: f32) -> f32 {
+        let points = GB_WAVE_POINTS as f32;
+        let phase = (phase * points).floor() / points;
+        let value = match (params.wave_type, &params.wavetable) {
+            (WaveType::Sawtooth, _) => 1.0 - phase * 2.0,
+            (WaveType::Wavetable, Some(wavetable)) => wavetable.sample(phase),
+            _ => (phase * TAU).sin(),
+        };
+        Chip::quantize_wave(value)
+    }
+
+    /// Features of the sound the chip lacks, which `snap` drops and the
+    /// chip mode leaves out.
+    pub fn unsupported(self, params: &SynthParams) -> Vec<String> {
+        let mut features = Vec::new();
+        let channel = self.channel(params.wave_type);
+        match (channel, params.wave_type) {
+            (_, WaveType::Square | WaveType::Noise) => {}
+            (Channel::Wave, WaveType::Sine | WaveType::Sawtooth | WaveType::Wavetable) => {}
+            (Channel::Triangle, WaveType::Sine) => {}
+            (Channel::Pulse, wave_type) => {
+                features.push(format!("{:?} wave, played as a pulse", wave_type))
+            }
+            (Channel::Triangle, wave_type) => {
+                features.push(format!("{:?} wave, played as the triangle", wave_type))
+            }
+            (_, wave_type) => features.push(format!("{:?} wave, played as a sine", wave_type)),
+        }
+        if channel == Channel::Triangle && (params.env_punch > 0.0 || params.volume_env.is_some()) {
+            features.push("volume changes on the triangle".to_string());
+        }
+
+        let defaults = SynthParams::default();
+        let mut check = |used: bool, feature: &str| {
+            if used {
+                features.push(feature.to_string());
+            }
+        };
+        check(params.ring_mod.is_some(), "ring modulation");
+        check(
+            params.lpf_freq != defaults.lpf_freq || params.lpf_ramp != defaults.lpf_ramp,
+            "low-pass filter",
+        );
+        check(
+            params.hpf_freq != defaults.hpf_freq || params.hpf_ramp != defaults.hpf_ramp,
+            "high-pass filter",
+        );
+        check(params.filter.is_some(), "state-variable filter");
+        check(params.filter_env.is_some(), "filter envelope");
+        check(
+            params.pha_offset != defaults.pha_offset || params.pha_ramp != defaults.pha_ramp,
+            "phaser",
+        );
+        check(
+            params.lfos.iter().any(|lfo| {
+                matches!(
+                    lfo.target,
+                    LfoTarget::LpfCutoff | LfoTarget::HpfCutoff | LfoTarget::PhaserOffset
+                ) && lfo.depth > 0.0
+            }),
+            "LFO on the filters or phaser",
+        );
+        let effects = &params.effects;
+        check(effects.distortion.mix > 0.0, "distortion");
+        check(effects.chorus.mix > 0.0, "chorus");
+        check(effects.delay.mix > 0.0, "delay");
+        check(effects.reverb.mix > 0.0, "reverb");
+        features
+    }
+
+    /// Converts a sound to one the chip can play, dropping what it
+    /// lacks: filters, phaser, ring modulation, post effects and duty
+    /// sweeps.
+    pub fn snap(self, params: &SynthParams) -> SynthParams {
+        let mut snapped = *params;
+        snapped.chip = Some(self);
+
+        let channel = self.channel(params.wave_type);
+        snapped.wave_type = match (channel, params.wave_type) {
+            (Channel::Pulse, _) => WaveType::Square,
+            (Channel::Noise, _) => WaveType::Noise,
+            (Channel::Wave, WaveType::Sawtooth | WaveType::Wavetable) => params.wave_type,
+            _ => WaveType::Sine,
+        };
+        if snapped.wave_type == WaveType::Wavetable {
+            let wavetable = params.wavetable.unwrap_or_default();
+            snapped.wavetable = Some(Wavetable::new(&Chip::wave_ram(|phase| {
+                wavetable.sample(phase)
+            })));
+        }
+
+        let duty = Chip::quantize_duty(0.5 - params.duty * 0.5);
+        snapped.duty = 1.0 - 2.0 * duty;
+        snapped.duty_ramp = 0.0;
+
+        let rate = (SAMPLE_RATE as usize * SUPERSAMPLING_FACTOR) as f32;
+        let hz = params.pitch_hz(SAMPLE_RATE);
+        let hz = match channel {
+            // the synth noise changes 32 times per period
+            Channel::Noise => self.noise_rate(32.0 * hz) / 32.0,
+            _ => rate / self.quantize_period(channel, rate / hz),
+        };
+        snapped = snapped.with_pitch_hz(hz, SAMPLE_RATE);
+
+        let defaults = SynthParams::default();
+        snapped.lpf_resonance = defaults.lpf_resonance;
+        snapped.lpf_freq = defaults.lpf_freq;
+        snapped.lpf_ramp = defaults.lpf_ramp;
+        snapped.hpf_freq = defaults.hpf_freq;
+        snapped.hpf_ramp = defaults.hpf_ramp;
+        snapped.filter = None;
+        snapped.filter_env = None;
+        snapped.pha_offset = defaults.pha_offset;
+        snapped.pha_ramp = defaults.pha_ramp;
+        snapped.ring_mod = None;
+        snapped.effects = defaults.effects;
+        snapped
+    }
+}
+
+/// 15-bit linear feedback shift register of the noise channels.
+pub fn lfsr_step(lfsr: u16) -> u16 {
+    let feedback = (lfsr ^ (lfsr >> 1)) & 1;
+    (lfsr >> 1) | (feedback << 14)
+}
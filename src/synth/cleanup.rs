@@ -0,0 +1,147 @@
+//! Post-render cleanup of a synthesized buffer.
+//!
+//! Removes DC offset, trims silent head and tail and applies short
+//! fades so the sound neither starts nor ends with a click.
+
+use super::SAMPLE_RATE;
+
+/// Pole of the DC-blocking filter, closer to 1.0 means a lower cutoff.
+const DC_BLOCK_POLE: f32 = 0.995;
+
+#[derive(Clone, Copy)]
+pub struct CleanupOptions {
+    /// Remove the DC offset with a one-pole high-pass filter.
+    pub dc_block: bool,
+    /// Samples whose magnitude stays below this value are trimmed from
+    /// the start and the end of the sound. Zero disables trimming.
+    pub trim_threshold: f32,
+    /// Length of the fade in, in seconds.
+    pub fade_in: f32,
+    /// Length of the fade out, in seconds.
+    pub fade_out: f32,
+    /// Length of the fades around each repeat restart, in seconds.
+    /// Restarts happen while rendering, so this one is handed to
+    /// `Synth::set_repeat_fade` before the render.
+    pub repeat_fade: f32,
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self {
+            dc_block: true,
+            trim_threshold: 0.0005,
+            fade_in: 0.002,
+            fade_out: 0.005,
+            repeat_fade: 0.002,
+        }
+    }
+}
+
+/// Applies every enabled cleanup step to the buffer, in order:
+/// DC removal, silence trimming and fades.
+///
+/// Returns the number of samples trimmed from the start, so positions
+/// in the original render can be mapped onto the cleaned buffer.
+pub fn cleanup(samples: &mut Vec<f32>, options: &CleanupOptions) -> usize {
+    if options.dc_block {
+        dc_block(samples);
+    }
+    let mut trimmed = 0;
+    if options.trim_threshold > 0.0 {
+        trimmed = trim_silence(samples, options.trim_threshold);
+    }
+    fade_in(samples, seconds_to_samples(options.fade_in));
+    fade_out(samples, seconds_to_samples(options.fade_out));
+
+    trimmed
+}
+
+/// Applies every enabled cleanup step to left and right frames, like
+/// `cleanup`. Both channels are trimmed together, until either of them
+/// is heard.
+pub fn cleanup_stereo(frames: &mut Vec<[f32; 2]>, options: &CleanupOptions) -> usize {
+    let mut channels = [0, 1].map(|channel| {
+        frames
+            .iter()
+            .map(|frame| frame[channel])
+            .collect::<Vec<_>>()
+    });
+    if options.dc_block {
+        for channel in &mut channels {
+            dc_block(channel);
+        }
+    }
+    let mut trimmed = 0;
+    if options.trim_threshold > 0.0 {
+        let loud = |i: &usize| {
+            channels
+                .iter()
+                .any(|channel| channel[*i].abs() >= options.trim_threshold)
+        };
+        let end = (0..frames.len()).rfind(loud).map_or(0, |end| end + 1);
+        trimmed = (0..end).find(loud).unwrap_or(end);
+        for channel in &mut channels {
+            channel.truncate(end);
+            channel.drain(..trimmed);
+        }
+    }
+    for channel in &mut channels {
+        fade_in(channel, seconds_to_samples(options.fade_in));
+        fade_out(channel, seconds_to_samples(options.fade_out));
+    }
+
+    let [left, right] = channels;
+    *frames = left.into_iter().zip(right).map(|(l, r)| [l, r]).collect();
+    trimmed
+}
+
+/// Removes the DC component with `y[n] = x[n] - x[n-1] + R * y[n-1]`.
+pub fn dc_block(samples: &mut [f32]) {
+    let mut prev_in = 0.0;
+    let mut prev_out = 0.0;
+    for sample in samples.iter_mut() {
+        let out = *sample - prev_in + DC_BLOCK_POLE * prev_out;
+        prev_in = *sample;
+        prev_out = out;
+        *sample = out;
+    }
+}
+
+/// Drops leading and trailing samples quieter than `threshold` and
+/// returns how many samples were removed from the start.
+pub fn trim_silence(samples: &mut Vec<f32>, threshold: f32) -> usize {
+    let Some(end) = samples.iter().rposition(|s| s.abs() >= threshold) else {
+        let trimmed = samples.len();
+        samples.clear();
+        return trimmed;
+    };
+    let start = samples
+        .iter()
+        .position(|s| s.abs() >= threshold)
+        .unwrap_or(0);
+
+    samples.truncate(end + 1);
+    samples.drain(..start);
+
+    start
+}
+
+/// Linearly ramps the first `length` samples up from silence.
+pub fn fade_in(samples: &mut [f32], length: usize) {
+    let length = length.min(samples.len());
+    for (i, sample) in samples.iter_mut().take(length).enumerate() {
+        *sample *= i as f32 / length as f32;
+    }
+}
+
+/// Linearly ramps the last `length` samples down to silence.
+pub fn fade_out(samples: &mut [f32], length: usize) {
+    let length = length.min(samples.len());
+    for (i, sample) in samples.iter_mut().rev().take(length).enumerate() {
+        *sample *= i as f32 / length as f32;
+    }
+}
+
+fn seconds_to_samples(seconds: f32) -> usize {
+    (seconds.max(0.0) * SAMPLE_RATE as f32) as usize
+}
@@ -0,0 +1,176 @@
+//! Convolution of rendered sounds with recorded impulse responses.
+//!
+//! Places a dry render in a real space, such as a cave, a metal corridor
+//! or an open field, captured as an impulse-response WAV. The impulse
+//! response is split into blocks convolved in the frequency domain
+//! (uniform partitioned overlap-add), so long responses stay fast.
+
+use std::path::Path;
+
+use super::SAMPLE_RATE;
+use crate::analysis::fft::{Complex, fft, ifft};
+use crate::analysis::resample;
+use crate::import::wav;
+
+/// Length of an impulse response partition, in samples.
+const BLOCK_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ConvolutionOptions {
+    /// Wet/dry balance, 0 keeps the dry sound only.
+    pub mix: f32,
+    /// Delay of the wet signal, in seconds.
+    pub pre_delay: f32,
+}
+
+impl Default for ConvolutionOptions {
+    fn default() -> Self {
+        Self {
+            mix: 0.5,
+            pre_delay: 0.0,
+        }
+    }
+}
+
+/// An impulse response at `SAMPLE_RATE`, scaled to unit energy so the
+/// wet signal keeps about the loudness of the dry one.
+#[derive(Clone, Debug)]
+pub struct ImpulseResponse {
+    samples: Vec<f32>,
+}
+
+impl ImpulseResponse {
+    /// Takes a response recorded at `sample_rate`.
+    pub fn new(samples: &[f32], sample_rate: u32) -> Self {
+        let mut samples = resample(samples, sample_rate, SAMPLE_RATE);
+        let energy: f32 = samples.iter().map(|sample| sample * sample).sum();
+        if energy > 0.0 {
+            let scale = 1.0 / energy.sqrt();
+            for sample in &mut samples {
+                *sample *= scale;
+            }
+        }
+        ImpulseResponse { samples }
+    }
+
+    /// Reads a response from a WAV file, mixed down to mono.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let (samples, sample_rate) = wav::load(path)?;
+        if samples.is_empty() {
+            anyhow::bail!("Empty impulse response");
+        }
+        Ok(ImpulseResponse::new(&samples, sample_rate))
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+/// Streaming convolution with an impulse response, `BLOCK_SIZE`
+/// samples at a time.
+pub struct Convolver {
+    /// Spectra of the impulse response partitions.
+    partitions: Vec<Vec<Complex>>,
+    /// Spectra of the latest input blocks, a ring indexed by `position`.
+    history: Vec<Vec<Complex>>,
+    position: usize,
+    /// Second half of the previous block, added to the next one.
+    overlap: Vec<f32>,
+}
+
+impl Convolver {
+    pub fn new(ir: &ImpulseResponse) -> Self {
+        let partitions: Vec<Vec<Complex>> = ir.samples.chunks(BLOCK_SIZE).map(spectrum).collect();
+        let history = vec![vec![Complex::default(); 2 * BLOCK_SIZE]; partitions.len()];
+
+        Convolver {
+            partitions,
+            history,
+            position: 0,
+            overlap: vec![0.0; BLOCK_SIZE],
+        }
+    }
+
+    /// Number of samples taken and returned by `process`.
+    pub fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    /// Convolves the next block of input, shorter blocks are padded with
+    /// silence. Returns the next `BLOCK_SIZE` output samples.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.partitions.is_empty() {
+            return vec![0.0; BLOCK_SIZE];
+        }
+
+        self.history[self.position] = spectrum(&input[..input.len().min(BLOCK_SIZE)]);
+
+        let count = self.partitions.len();
+        let mut sum = vec![Complex::default(); 2 * BLOCK_SIZE];
+        for (k, partition) in self.partitions.iter().enumerate() {
+            let block = &self.history[(self.position + count - k) % count];
+            for ((out, x), h) in sum.iter_mut().zip(block).zip(partition) {
+                *out = *out + *x * *h;
+            }
+        }
+        self.position = (self.position + 1) % count;
+
+        ifft(&mut sum);
+        let output = sum[..BLOCK_SIZE]
+            .iter()
+            .zip(&self.overlap)
+            .map(|(value, overlap)| value.re + overlap)
+            .collect();
+        for (overlap, value) in self.overlap.iter_mut().zip(&sum[BLOCK_SIZE..]) {
+            *overlap = value.re;
+        }
+        output
+    }
+}
+
+/// Mixes the dry sound with its convolution by `ir`. The result is
+/// longer than the input by the pre-delay and the length of the
+/// response, so the reverberation rings out.
+pub fn convolve(dry: &[f32], ir: &ImpulseResponse, options: &ConvolutionOptions) -> Vec<f32> {
+    let pre_delay = (options.pre_delay.max(0.0) * SAMPLE_RATE as f32) as usize;
+    let wet_length = dry.len() + ir.samples.len().saturating_sub(1);
+    let mix = options.mix.clamp(0.0, 1.0);
+
+    let mut output: Vec<f32> = dry.iter().map(|sample| sample * (1.0 - mix)).collect();
+    output.resize(pre_delay + wet_length, 0.0);
+
+    let mut convolver = Convolver::new(ir);
+    for start in (0..wet_length).step_by(BLOCK_SIZE) {
+        let input = dry.get(start..).unwrap_or(&[]);
+        let block = convolver.process(&input[..input.len().min(BLOCK_SIZE)]);
+        let out = &mut output[pre_delay + start..];
+        for (out, wet) in out.iter_mut().zip(block) {
+            *out += wet * mix;
+        }
+    }
+    output
+}
+
+/// Convolves both channels of a stereo sound, see `convolve`.
+pub fn convolve_stereo(
+    frames: &[[f32; 2]],
+    ir: &ImpulseResponse,
+    options: &ConvolutionOptions,
+) -> Vec<[f32; 2]> {
+    let [left, right] = [0, 1].map(|channel| {
+        let samples: Vec<f32> = frames.iter().map(|frame| frame[channel]).collect();
+        convolve(&samples, ir, options)
+    });
+    left.into_iter().zip(right).map(|(l, r)| [l, r]).collect()
+}
+
+/// Spectrum of a block zero-padded to twice `BLOCK_SIZE`.
+fn spectrum(samples: &[f32]) -> Vec<Complex> {
+    let mut buffer = vec![Complex::default(); 2 * BLOCK_SIZE];
+    for (value, &sample) in buffer.iter_mut().zip(samples) {
+        value.re = sample;
+    }
+    fft(&mut buffer);
+    buffer
+}
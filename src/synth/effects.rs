@@ -0,0 +1,148 @@
+//! Effects processing the synthesized sound.
+//!
+//! Every `Synth` owns two ordered chains of effects. The voice chain
+//! works on the supersampled oscillator before the volume envelope and
+//! holds the classic low-pass, high-pass and phaser trio, or the
+//! state-variable filter in place of the first two. The post chain
+//! works on the enveloped sound at `SAMPLE_RATE` and holds distortion,
+//! chorus, delay and reverb, whose tails keep sounding after the
+//! envelope ends. The built-in effects read their settings from
+//! `SynthParams`; custom effects, including ones defined outside this
+//! crate, can be added anywhere in either chain.
+//!
+//! Once per output sample each effect gets the current LFO modulation
+//! through `Effect::control`, then its samples go through the effects
+//! in order.
+
+pub mod chorus;
+pub mod delay;
+pub mod distortion;
+pub mod filter;
+pub mod phaser;
+pub mod reverb;
+pub mod svf;
+
+use serde::{Deserialize, Serialize};
+
+use super::lfo::Modulation;
+use super::live::LiveParam;
+use super::params::SynthParams;
+
+pub use chorus::{Chorus, ChorusParams};
+pub use delay::{Delay, DelayParams};
+pub use distortion::{Distortion, DistortionParams};
+pub use filter::{HighPass, LowPass};
+pub use phaser::Phaser;
+pub use reverb::{Reverb, ReverbParams};
+pub use svf::{FilterMode, FilterParams, StateVariable};
+
+/// Level below which a fading repeat counts as silent, -60dB.
+const SILENCE: f32 = 0.001;
+
+pub trait Effect: Send {
+    /// Prepares the effect for a new sound. Repeats of the same sound
+    /// don't reset it. `sample_rate` is the rate of the samples given to
+    /// `process`, supersampling included.
+    fn reset(&mut self, params: &SynthParams, sample_rate: f32);
+
+    /// Called once per output sample, before its block is processed.
+    fn control(&mut self, _modulation: &Modulation) {}
+
+    /// Follows a live parameter moving from `from` to `to` while the
+    /// sound plays.
+    fn set_live(&mut self, _param: LiveParam, _from: f32, _to: f32, _params: &SynthParams) {}
+
+    fn process(&mut self, sample: f32) -> f32;
+
+    /// Processes consecutive samples in place.
+    fn process_block(&mut self, block: &mut [f32]) {
+        for sample in block {
+            *sample = self.process(*sample);
+        }
+    }
+
+    /// Number of samples the effect keeps sounding once its input falls
+    /// silent. Only used in the post chain.
+    fn tail(&self) -> usize {
+        0
+    }
+}
+
+/// Settings of the post-synthesis effects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EffectParams {
+    pub distortion: DistortionParams,
+    pub chorus: ChorusParams,
+    pub delay: DelayParams,
+    pub reverb: ReverbParams,
+}
+
+impl EffectParams {
+    /// Interpolates every setting, see `SynthParams::lerp`.
+    pub fn lerp(a: &EffectParams, b: &EffectParams, t: f32) -> EffectParams {
+        let mut params = *a;
+        let mut other = *b;
+        for (value, target) in params.values_mut().into_iter().zip(other.values_mut()) {
+            *value += (*target - *value) * t;
+        }
+        params
+    }
+
+    /// Brings every setting back into 0..1.
+    pub fn clamp_ranges(&mut self) {
+        for value in self.values_mut() {
+            *value = value.clamp(0.0, 1.0);
+        }
+    }
+
+    fn values_mut(&mut self) -> [&mut f32; 13] {
+        [
+            &mut self.distortion.drive,
+            &mut self.distortion.mix,
+            &mut self.chorus.rate,
+            &mut self.chorus.depth,
+            &mut self.chorus.delay,
+            &mut self.chorus.feedback,
+            &mut self.chorus.mix,
+            &mut self.delay.time,
+            &mut self.delay.feedback,
+            &mut self.delay.mix,
+            &mut self.reverb.room_size,
+            &mut self.reverb.damping,
+            &mut self.reverb.mix,
+        ]
+    }
+}
+
+/// The classic effects, in the order the original synth applied them,
+/// with the state-variable filter standing in for the low-pass and
+/// high-pass when `SynthParams::filter` is set.
+pub fn default_chain() -> Vec<Box<dyn Effect>> {
+    vec![
+        Box::new(LowPass::default()),
+        Box::new(HighPass::default()),
+        Box::new(StateVariable::default()),
+        Box::new(Phaser::default()),
+    ]
+}
+
+/// The effects set by `EffectParams`, applied after the envelope.
+pub fn default_post_chain() -> Vec<Box<dyn Effect>> {
+    vec![
+        Box::new(Distortion::default()),
+        Box::new(Chorus::default()),
+        Box::new(Delay::default()),
+        Box::new(Reverb::default()),
+    ]
+}
+
+/// Number of trips through a feedback loop before the signal falls
+/// silent, counting the first one.
+fn repeats(feedback: f32) -> usize {
+    if feedback <= 0.0 {
+        1
+    } else {
+        (SILENCE.ln() / feedback.min(0.999).ln()).ceil() as usize + 1
+    }
+}
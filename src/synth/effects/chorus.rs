@@ -0,0 +1,104 @@
+//! Chorus and flanger, mixing the sound with a copy whose delay wobbles.
+//!
+//! Short delays with feedback give the metallic sweep of a flanger,
+//! longer ones without feedback a chorus.
+
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Effect, repeats};
+use crate::synth::params::SynthParams;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChorusParams {
+    /// Speed of the wobble, 0..1 maps to 0.1Hz to 5Hz.
+    pub rate: f32,
+    /// Width of the wobble, 0..1 maps up to 5ms.
+    pub depth: f32,
+    /// Shortest delay, 0..1 maps to 1ms to 30ms.
+    pub delay: f32,
+    /// Amount of the delayed copy fed back into itself, 0..1.
+    pub feedback: f32,
+    /// Wet/dry balance, 0 switches the chorus off.
+    pub mix: f32,
+}
+
+impl Default for ChorusParams {
+    fn default() -> Self {
+        ChorusParams {
+            rate: 0.3,
+            depth: 0.5,
+            delay: 0.5,
+            feedback: 0.0,
+            mix: 0.0,
+        }
+    }
+}
+
+impl ChorusParams {
+    /// Frequency of the wobble in Hz.
+    pub fn frequency(&self) -> f32 {
+        0.1 + self.rate.clamp(0.0, 1.0).powf(2.0) * 4.9
+    }
+}
+
+#[derive(Default)]
+pub struct Chorus {
+    buffer: Vec<f32>,
+    position: usize,
+    phase: f32,
+    phase_step: f32,
+    /// Shortest delay in samples.
+    delay: f32,
+    /// Width of the wobble in samples.
+    depth: f32,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Effect for Chorus {
+    fn reset(&mut self, params: &SynthParams, sample_rate: f32) {
+        let params = params.effects.chorus;
+        self.buffer.clear();
+        self.position = 0;
+        self.phase = 0.0;
+        self.phase_step = params.frequency() / sample_rate * TAU;
+        self.delay = (0.001 + params.delay.clamp(0.0, 1.0) * 0.029) * sample_rate;
+        self.depth = params.depth.clamp(0.0, 1.0) * 0.005 * sample_rate;
+        self.feedback = params.feedback.clamp(0.0, 1.0) * 0.9;
+        self.mix = params.mix.clamp(0.0, 1.0);
+        if self.mix > 0.0 {
+            let length = (self.delay + self.depth) as usize + 2;
+            self.buffer.resize(length, 0.0);
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.buffer.is_empty() {
+            return sample;
+        }
+
+        let length = self.buffer.len();
+        let delay = self.delay + self.depth * (0.5 + 0.5 * self.phase.sin());
+        self.phase = (self.phase + self.phase_step) % TAU;
+
+        // linear interpolation between the two samples around the delay
+        let read = (self.position + length) as f32 - delay;
+        let fraction = read.fract();
+        let index = read as usize;
+        let a = self.buffer[index % length];
+        let b = self.buffer[(index + 1) % length];
+        let delayed = a + (b - a) * fraction;
+
+        self.buffer[self.position] = sample + delayed * self.feedback;
+        self.position = (self.position + 1) % length;
+
+        sample * (1.0 - self.mix) + delayed * self.mix
+    }
+
+    fn tail(&self) -> usize {
+        self.buffer.len() * repeats(self.feedback)
+    }
+}
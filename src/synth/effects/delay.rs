@@ -0,0 +1,72 @@
+//! Feedback delay, repeating the sound as fading echoes.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Effect, repeats};
+use crate::synth::params::SynthParams;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DelayParams {
+    /// Time between echoes, 0..1 maps to 20ms to 1s.
+    pub time: f32,
+    /// Level of each echo relative to the previous one, 0..1.
+    pub feedback: f32,
+    /// Wet/dry balance, 0 switches the delay off.
+    pub mix: f32,
+}
+
+impl Default for DelayParams {
+    fn default() -> Self {
+        DelayParams {
+            time: 0.5,
+            feedback: 0.4,
+            mix: 0.0,
+        }
+    }
+}
+
+impl DelayParams {
+    /// Time between echoes in seconds.
+    pub fn seconds(&self) -> f32 {
+        0.02 + self.time.clamp(0.0, 1.0).powf(2.0) * 0.98
+    }
+}
+
+#[derive(Default)]
+pub struct Delay {
+    buffer: Vec<f32>,
+    position: usize,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Effect for Delay {
+    fn reset(&mut self, params: &SynthParams, sample_rate: f32) {
+        let params = params.effects.delay;
+        self.buffer.clear();
+        self.position = 0;
+        self.feedback = params.feedback.clamp(0.0, 1.0) * 0.9;
+        self.mix = params.mix.clamp(0.0, 1.0);
+        if self.mix > 0.0 {
+            let length = (params.seconds() * sample_rate) as usize;
+            self.buffer.resize(length.max(1), 0.0);
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.buffer.is_empty() {
+            return sample;
+        }
+
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = sample + delayed * self.feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+
+        sample * (1.0 - self.mix) + delayed * self.mix
+    }
+
+    fn tail(&self) -> usize {
+        self.buffer.len() * repeats(self.feedback)
+    }
+}
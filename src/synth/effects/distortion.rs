@@ -0,0 +1,50 @@
+//! Waveshaper distortion, soft clipping the sound.
+
+use serde::{Deserialize, Serialize};
+
+use super::Effect;
+use crate::synth::params::SynthParams;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DistortionParams {
+    /// Gain pushing the sound into the clipping curve, 0..1.
+    pub drive: f32,
+    /// Wet/dry balance, 0 switches the distortion off.
+    pub mix: f32,
+}
+
+impl Default for DistortionParams {
+    fn default() -> Self {
+        DistortionParams {
+            drive: 0.5,
+            mix: 0.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Distortion {
+    gain: f32,
+    /// Scale bringing a full scale input back to full scale.
+    normalize: f32,
+    mix: f32,
+}
+
+impl Effect for Distortion {
+    fn reset(&mut self, params: &SynthParams, _sample_rate: f32) {
+        let params = params.effects.distortion;
+        self.gain = 1.0 + params.drive.clamp(0.0, 1.0).powf(2.0) * 50.0;
+        self.normalize = 1.0 / self.gain.tanh();
+        self.mix = params.mix.clamp(0.0, 1.0);
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.mix == 0.0 {
+            return sample;
+        }
+
+        let shaped = (sample * self.gain).tanh() * self.normalize;
+        sample * (1.0 - self.mix) + shaped * self.mix
+    }
+}
@@ -0,0 +1,141 @@
+//! The classic resonant low-pass and the high-pass filters.
+
+use super::Effect;
+use crate::synth::lfo::{LfoTarget, Modulation};
+use crate::synth::live::LiveParam;
+use crate::synth::params::SynthParams;
+
+/// Resonant low-pass with a cutoff sweep, set by the `lpf_*` params.
+/// Bypassed when `SynthParams::filter` replaces it.
+#[derive(Default)]
+pub struct LowPass {
+    /// Whether the cutoff is fully open and the input passes untouched.
+    bypass: bool,
+    replaced: bool,
+    /// Whether the filter envelope or an LFO moves the cutoff, which
+    /// keeps the filter active when fully open.
+    modulated: bool,
+    position: f32,
+    velocity: f32,
+    cutoff: f32,
+    sweep: f32,
+    damping: f32,
+    /// Cutoff multiplier from the LFOs and the filter envelope.
+    modulation: f32,
+}
+
+impl Effect for LowPass {
+    fn reset(&mut self, params: &SynthParams, _sample_rate: f32) {
+        self.replaced = params.filter.is_some();
+        self.modulated = params.filter_env.is_some()
+            || params
+                .lfos
+                .iter()
+                .any(|lfo| lfo.target == LfoTarget::LpfCutoff);
+        self.bypass = (params.lpf_freq == 1.0 && !self.modulated) || self.replaced;
+        self.position = 0.0;
+        self.velocity = 0.0;
+        self.cutoff = params.lpf_freq.powf(3.0) * 0.1;
+        self.sweep = 1.0 + params.lpf_ramp * 0.0001;
+        self.damping = 5.0 / (1.0 + params.lpf_resonance.powf(2.0) * 20.0) * (0.01 + self.cutoff);
+        if self.damping > 0.8 {
+            self.damping = 0.8;
+        }
+        self.modulation = 1.0;
+    }
+
+    fn control(&mut self, modulation: &Modulation) {
+        self.modulation = modulation.lpf;
+    }
+
+    fn set_live(&mut self, param: LiveParam, from: f32, to: f32, params: &SynthParams) {
+        if !matches!(param, LiveParam::LpfFreq) {
+            return;
+        }
+
+        let old_w = from.powf(3.0) * 0.1;
+        let new_w = to.powf(3.0) * 0.1;
+        if old_w > 0.0 {
+            self.cutoff *= new_w / old_w;
+        } else {
+            self.cutoff = new_w;
+        }
+        self.damping =
+            (5.0 / (1.0 + params.lpf_resonance.powf(2.0) * 20.0) * (0.01 + self.cutoff)).min(0.8);
+        self.bypass = (to == 1.0 && !self.modulated) || self.replaced;
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        self.cutoff *= self.sweep;
+        self.cutoff = self.cutoff.clamp(0.0, 0.1);
+        let cutoff = (self.cutoff * self.modulation).min(0.1);
+
+        if !self.bypass {
+            self.velocity += (sample - self.position) * cutoff;
+            self.velocity -= self.velocity * self.damping;
+        } else {
+            self.position = sample;
+            self.velocity = 0.0;
+        }
+        self.position += self.velocity;
+        self.position
+    }
+}
+
+/// One-pole high-pass with a cutoff sweep, set by the `hpf_*` params.
+/// Bypassed when `SynthParams::filter` replaces it.
+#[derive(Default)]
+pub struct HighPass {
+    replaced: bool,
+    position: f32,
+    previous: f32,
+    cutoff: f32,
+    sweep: f32,
+    /// Cutoff of the current output sample, modulation included.
+    modulated: f32,
+}
+
+impl Effect for HighPass {
+    fn reset(&mut self, params: &SynthParams, _sample_rate: f32) {
+        self.replaced = params.filter.is_some();
+        self.position = 0.0;
+        self.previous = 0.0;
+        self.cutoff = params.hpf_freq.powf(2.0) * 0.1;
+        self.sweep = 1.0 + params.hpf_ramp * 0.0003;
+        self.modulated = self.cutoff;
+    }
+
+    fn control(&mut self, modulation: &Modulation) {
+        if self.sweep != 0.0 {
+            self.cutoff *= self.sweep;
+
+            self.cutoff = self.cutoff.clamp(0.00001, 0.1);
+        }
+        self.modulated = self.cutoff * modulation.hpf;
+    }
+
+    fn set_live(&mut self, param: LiveParam, from: f32, to: f32, _params: &SynthParams) {
+        if !matches!(param, LiveParam::HpfFreq) {
+            return;
+        }
+
+        let old_hp = from.powf(2.0) * 0.1;
+        let new_hp = to.powf(2.0) * 0.1;
+        if old_hp > 0.0 {
+            self.cutoff *= new_hp / old_hp;
+        } else {
+            self.cutoff = new_hp;
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.replaced {
+            return sample;
+        }
+
+        self.position += sample - self.previous;
+        self.previous = sample;
+        self.position -= self.position * self.modulated;
+        self.position
+    }
+}
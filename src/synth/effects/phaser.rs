@@ -0,0 +1,65 @@
+//! The classic phaser, mixing the input with a swept delayed copy.
+
+use super::Effect;
+use crate::synth::lfo::Modulation;
+use crate::synth::params::SynthParams;
+
+const BUFFER_SIZE: usize = 1024;
+
+/// Phaser set by the `pha_offset` and `pha_ramp` params.
+pub struct Phaser {
+    buffer: [f32; BUFFER_SIZE],
+    position: i32,
+    offset: f32,
+    sweep: f32,
+    /// Delay of the current output sample in samples.
+    delay: i32,
+}
+
+impl Default for Phaser {
+    fn default() -> Self {
+        Phaser {
+            buffer: [0.0; BUFFER_SIZE],
+            position: 0,
+            offset: 0.0,
+            sweep: 0.0,
+            delay: 0,
+        }
+    }
+}
+
+impl Effect for Phaser {
+    fn reset(&mut self, params: &SynthParams, _sample_rate: f32) {
+        self.offset = params.pha_offset.powf(2.0) * 1020.0;
+        if params.pha_offset < 0.0 {
+            self.offset = -self.offset;
+        }
+        self.sweep = params.pha_ramp.powf(2.0) * 1.0;
+        if params.pha_ramp < 0.0 {
+            self.sweep = -self.sweep;
+        }
+        self.delay = self.offset.abs() as i32;
+        self.position = 0;
+
+        self.buffer.fill(0.0);
+    }
+
+    fn control(&mut self, modulation: &Modulation) {
+        self.offset += self.sweep;
+        self.delay = ((self.offset + modulation.phaser) as i32).abs();
+        if self.delay > 1023 {
+            self.delay = 1023
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let index = (self.position & 1023) as usize;
+        self.buffer[index] = sample;
+
+        let index = ((self.position - self.delay + 1024) & 1023) as usize;
+        let output = sample + self.buffer[index];
+
+        self.position = (self.position + 1) & 1023;
+        output
+    }
+}
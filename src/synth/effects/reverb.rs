@@ -0,0 +1,132 @@
+//! Small algorithmic reverb after Freeverb: parallel damped comb
+//! filters followed by allpass diffusers.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Effect, repeats};
+use crate::synth::params::SynthParams;
+
+/// Comb filter lengths in samples at 44.1kHz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Allpass filter lengths in samples at 44.1kHz.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+const ALLPASS_FEEDBACK: f32 = 0.5;
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReverbParams {
+    /// Decay time, 0..1 from a small room to a large hall.
+    pub room_size: f32,
+    /// High frequency absorption, 0..1.
+    pub damping: f32,
+    /// Wet/dry balance, 0 switches the reverb off.
+    pub mix: f32,
+}
+
+impl Default for ReverbParams {
+    fn default() -> Self {
+        ReverbParams {
+            room_size: 0.5,
+            damping: 0.5,
+            mix: 0.0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.position] = input + self.store * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Default)]
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * ALLPASS_FEEDBACK;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+#[derive(Default)]
+pub struct Reverb {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+    feedback: f32,
+    damping: f32,
+    mix: f32,
+}
+
+impl Effect for Reverb {
+    fn reset(&mut self, params: &SynthParams, sample_rate: f32) {
+        let params = params.effects.reverb;
+        self.feedback = 0.7 + params.room_size.clamp(0.0, 1.0) * 0.28;
+        self.damping = params.damping.clamp(0.0, 1.0) * 0.4;
+        self.mix = params.mix.clamp(0.0, 1.0);
+
+        self.combs.clear();
+        self.allpasses.clear();
+        if self.mix == 0.0 {
+            return;
+        }
+
+        let scale = |length: usize| ((length as f32 * sample_rate / 44_100.0) as usize).max(1);
+        self.combs = COMB_TUNING
+            .map(|length| Comb {
+                buffer: vec![0.0; scale(length)],
+                ..Default::default()
+            })
+            .into();
+        self.allpasses = ALLPASS_TUNING
+            .map(|length| Allpass {
+                buffer: vec![0.0; scale(length)],
+                ..Default::default()
+            })
+            .into();
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        if self.combs.is_empty() {
+            return sample;
+        }
+
+        let input = sample * INPUT_GAIN;
+        let mut wet = 0.0;
+        for comb in &mut self.combs {
+            wet += comb.process(input, self.feedback, self.damping);
+        }
+        for allpass in &mut self.allpasses {
+            wet = allpass.process(wet);
+        }
+
+        sample * (1.0 - self.mix) + wet * WET_GAIN * self.mix
+    }
+
+    fn tail(&self) -> usize {
+        let longest_comb = self.combs.iter().map(|comb| comb.buffer.len()).max();
+        let diffusion: usize = self.allpasses.iter().map(|pass| pass.buffer.len()).sum();
+        match longest_comb {
+            Some(length) => length * repeats(self.feedback) + diffusion * repeats(ALLPASS_FEEDBACK),
+            None => 0,
+        }
+    }
+}
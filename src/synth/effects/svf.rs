@@ -0,0 +1,147 @@
+//! Resonant state-variable filter with low-pass, high-pass, band-pass
+//! and notch outputs.
+//!
+//! Unlike the classic filters it takes its cutoff in Hz. It uses the
+//! trapezoidal (TPT) form, which stays stable at any cutoff and
+//! resonance. It doesn't follow the live `lpf_freq` and `hpf_freq`,
+//! which are normalized cutoffs of the classic filters.
+
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use super::Effect;
+use crate::synth::SAMPLE_RATE;
+use crate::synth::lfo::Modulation;
+use crate::synth::params::SynthParams;
+
+/// Lowest cutoff, in Hz.
+pub const MIN_CUTOFF: f32 = 20.0;
+/// Highest cutoff, in Hz.
+pub const MAX_CUTOFF: f32 = 20_000.0;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    #[default]
+    LowPass,
+    HighPass,
+    /// Unity gain at the cutoff, narrower as the resonance grows.
+    BandPass,
+    Notch,
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 4] = [
+        FilterMode::LowPass,
+        FilterMode::HighPass,
+        FilterMode::BandPass,
+        FilterMode::Notch,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterParams {
+    pub mode: FilterMode,
+    /// Starting cutoff in Hz. The LFOs and the filter envelope aimed at
+    /// the low-pass cutoff move it in every mode.
+    pub cutoff: f32,
+    /// Emphasis around the cutoff, 0..1, close to 1 the filter rings.
+    pub resonance: f32,
+    /// Cutoff change in octaves per second.
+    pub sweep: f32,
+}
+
+impl Default for FilterParams {
+    fn default() -> Self {
+        FilterParams {
+            mode: FilterMode::LowPass,
+            cutoff: 2000.0,
+            resonance: 0.5,
+            sweep: 0.0,
+        }
+    }
+}
+
+impl FilterParams {
+    /// Brings every setting back into the range of its GUI slider.
+    pub fn clamp_ranges(&mut self) {
+        self.cutoff = self.cutoff.clamp(MIN_CUTOFF, MAX_CUTOFF);
+        self.resonance = self.resonance.clamp(0.0, 1.0);
+        self.sweep = self.sweep.clamp(-8.0, 8.0);
+    }
+}
+
+/// Runs the filter set by `SynthParams::filter`, passing the sound
+/// through untouched when there is none.
+#[derive(Default)]
+pub struct StateVariable {
+    params: Option<FilterParams>,
+    sample_rate: f32,
+    /// Cutoff in Hz, sweep included.
+    cutoff: f32,
+    /// Cutoff multiplier per output sample.
+    sweep: f32,
+    /// Damping, 2 without resonance down to nearly 0.
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    ic1: f32,
+    ic2: f32,
+}
+
+impl Effect for StateVariable {
+    fn reset(&mut self, params: &SynthParams, sample_rate: f32) {
+        self.params = params.filter;
+        self.sample_rate = sample_rate;
+        self.ic1 = 0.0;
+        self.ic2 = 0.0;
+        if let Some(filter) = params.filter {
+            self.cutoff = filter.cutoff.clamp(MIN_CUTOFF, MAX_CUTOFF);
+            self.sweep = (filter.sweep / SAMPLE_RATE as f32).exp2();
+            self.k = 2.0 - filter.resonance.clamp(0.0, 1.0) * 1.98;
+            self.set_cutoff(self.cutoff);
+        }
+    }
+
+    fn control(&mut self, modulation: &Modulation) {
+        if self.params.is_none() {
+            return;
+        }
+
+        self.cutoff = (self.cutoff * self.sweep).clamp(MIN_CUTOFF, MAX_CUTOFF);
+        self.set_cutoff((self.cutoff * modulation.lpf).clamp(MIN_CUTOFF, MAX_CUTOFF));
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let Some(params) = self.params else {
+            return sample;
+        };
+
+        let v3 = sample - self.ic2;
+        let v1 = self.a1 * self.ic1 + self.a2 * v3;
+        let v2 = self.ic2 + self.a2 * self.ic1 + self.a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+
+        let low = v2;
+        let band = v1;
+        let high = sample - self.k * band - low;
+        match params.mode {
+            FilterMode::LowPass => low,
+            FilterMode::HighPass => high,
+            FilterMode::BandPass => band * self.k,
+            FilterMode::Notch => low + high,
+        }
+    }
+}
+
+impl StateVariable {
+    fn set_cutoff(&mut self, cutoff: f32) {
+        let g = (PI * cutoff / self.sample_rate).tan();
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+}
@@ -0,0 +1,194 @@
+//! Multi-segment envelopes with curved segments.
+//!
+//! An envelope is a list of breakpoints, each with a time in seconds
+//! and a level. Every segment bends according to the curve of the
+//! breakpoint it ends on, so decays can be exponential and swells
+//! logarithmic. When a sound has no custom envelope the classic
+//! attack/sustain/decay envelope is used instead.
+
+use serde::{Deserialize, Serialize};
+
+use super::SAMPLE_RATE;
+use super::params::SynthParams;
+
+/// Most breakpoints an envelope can hold.
+pub const MAX_BREAKPOINTS: usize = 8;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Breakpoint {
+    /// Time from the start of the sound, in seconds.
+    pub time: f32,
+    pub level: f32,
+    /// Bend of the segment ending on this point. Zero is linear,
+    /// positive values start slow and end fast, negative values start
+    /// fast and end slow.
+    pub curve: f32,
+}
+
+impl Breakpoint {
+    pub const fn new(time: f32, level: f32, curve: f32) -> Self {
+        Breakpoint { time, level, curve }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<Breakpoint>", into = "Vec<Breakpoint>")]
+pub struct Envelope {
+    points: [Breakpoint; MAX_BREAKPOINTS],
+    len: usize,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope::new(&[
+            Breakpoint::new(0.0, 1.0, 0.0),
+            Breakpoint::new(0.5, 0.0, -4.0),
+        ])
+    }
+}
+
+impl Envelope {
+    /// Creates an envelope from up to `MAX_BREAKPOINTS` points, sorted
+    /// by time.
+    pub fn new(points: &[Breakpoint]) -> Self {
+        let mut envelope = Envelope {
+            points: [Breakpoint::default(); MAX_BREAKPOINTS],
+            len: 0,
+        };
+        for &point in points.iter().take(MAX_BREAKPOINTS) {
+            envelope.insert(point);
+        }
+        envelope
+    }
+
+    /// Creates an envelope from points already sorted by time, usable in
+    /// constants. Points past `MAX_BREAKPOINTS` are dropped.
+    pub const fn from_sorted(points: &[Breakpoint]) -> Self {
+        let mut envelope = Envelope {
+            points: [Breakpoint::new(0.0, 0.0, 0.0); MAX_BREAKPOINTS],
+            len: 0,
+        };
+        while envelope.len < points.len() && envelope.len < MAX_BREAKPOINTS {
+            envelope.points[envelope.len] = points[envelope.len];
+            envelope.len += 1;
+        }
+        envelope
+    }
+
+    /// A flat envelope staying at `level`.
+    pub fn flat(level: f32) -> Self {
+        Envelope::new(&[
+            Breakpoint::new(0.0, level, 0.0),
+            Breakpoint::new(0.5, level, 0.0),
+        ])
+    }
+
+    /// Breakpoints equivalent to the classic volume envelope of the
+    /// given sound, punch included.
+    pub fn from_classic(params: &SynthParams) -> Self {
+        let seconds = |value: f32| value.powf(2.0) * 100000.0 / SAMPLE_RATE as f32;
+        let attack = seconds(params.env_attack);
+        let sustain = attack + seconds(params.env_sustain);
+        let decay = sustain + seconds(params.env_decay);
+
+        Envelope::new(&[
+            Breakpoint::new(0.0, 0.0, 0.0),
+            Breakpoint::new(attack, 1.0 + 2.0 * params.env_punch, 0.0),
+            Breakpoint::new(sustain, 1.0, 0.0),
+            Breakpoint::new(decay, 0.0, 0.0),
+        ])
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points[..self.len]
+    }
+
+    /// Breakpoints for editing. Times should stay in increasing order.
+    pub fn points_mut(&mut self) -> &mut [Breakpoint] {
+        &mut self.points[..self.len]
+    }
+
+    /// Adds a point in time order, returning its index or `None` when
+    /// the envelope is full.
+    pub fn insert(&mut self, point: Breakpoint) -> Option<usize> {
+        if self.len == MAX_BREAKPOINTS {
+            return None;
+        }
+
+        let index = self.points().partition_point(|p| p.time <= point.time);
+        self.points.copy_within(index..self.len, index + 1);
+        self.points[index] = point;
+        self.len += 1;
+        Some(index)
+    }
+
+    /// Removes a point, keeping at least two.
+    pub fn remove(&mut self, index: usize) {
+        if self.len > 2 && index < self.len {
+            self.points.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+
+    /// Time of the last breakpoint, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.points().last().map_or(0.0, |point| point.time)
+    }
+
+    /// Time of the point a held sound stays on until released, the last
+    /// one before the final segment.
+    pub fn sustain_time(&self) -> f32 {
+        let points = self.points();
+        points[..points.len().saturating_sub(1)]
+            .last()
+            .map_or(0.0, |point| point.time)
+    }
+
+    /// Level at `time` seconds. Before the first point and after the
+    /// last one the level of that point is held.
+    pub fn level(&self, time: f32) -> f32 {
+        let points = self.points();
+        let Some(first) = points.first() else {
+            return 0.0;
+        };
+
+        let index = points.partition_point(|p| p.time <= time);
+        if index == 0 {
+            return first.level;
+        }
+        if index == points.len() {
+            return points[index - 1].level;
+        }
+
+        let (from, to) = (points[index - 1], points[index]);
+        let span = to.time - from.time;
+        if span <= 0.0 {
+            return to.level;
+        }
+
+        let x = shape((time - from.time) / span, to.curve);
+        from.level + (to.level - from.level) * x
+    }
+}
+
+impl From<Vec<Breakpoint>> for Envelope {
+    fn from(points: Vec<Breakpoint>) -> Self {
+        Envelope::new(&points)
+    }
+}
+
+impl From<Envelope> for Vec<Breakpoint> {
+    fn from(envelope: Envelope) -> Self {
+        envelope.points().to_vec()
+    }
+}
+
+/// Bends a 0..1 ramp, keeping both ends in place.
+fn shape(x: f32, curve: f32) -> f32 {
+    if curve.abs() < 0.001 {
+        x
+    } else {
+        ((curve * x).exp() - 1.0) / (curve.exp() - 1.0)
+    }
+}
@@ -0,0 +1,224 @@
+//! Interactive "breed and evolve" search over sound parameters.
+//!
+//! A population of candidates is scored, by a listener or by a fitness
+//! function, and the best ones are bred into the next generation. The
+//! whole session is driven by one seed, so replaying the same seed with
+//! the same scores yields the same sounds.
+
+use rand::prelude::*;
+
+use super::params::SynthParams;
+use super::presets::{SoundType, SynthPreset};
+
+/// Generators used to seed the first generation.
+const GENERATORS: [SoundType; 8] = [
+    SoundType::PickupCoin,
+    SoundType::LaserShoot,
+    SoundType::Explosion,
+    SoundType::PowerUp,
+    SoundType::HitHurt,
+    SoundType::Jump,
+    SoundType::BlipSelect,
+    SoundType::Bell,
+];
+
+#[derive(Clone, Copy)]
+pub struct EvolveOptions {
+    /// Number of candidates in each generation.
+    pub population: usize,
+    /// Probability for each parameter of a child to be mutated.
+    pub mutation_rate: f32,
+    /// Largest change a mutation applies to a parameter.
+    pub mutation_strength: f32,
+    /// Number of best candidates copied unchanged into the next
+    /// generation.
+    pub elitism: usize,
+}
+
+impl Default for EvolveOptions {
+    fn default() -> Self {
+        Self {
+            population: 9,
+            mutation_rate: 0.3,
+            mutation_strength: 0.1,
+            elitism: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Candidate {
+    pub params: SynthParams,
+    /// Score of the candidate, higher is better. Unrated candidates
+    /// have a fitness of zero.
+    pub fitness: f32,
+}
+
+pub struct Evolution {
+    pub options: EvolveOptions,
+    pub candidates: Vec<Candidate>,
+    generation: usize,
+    rng: StdRng,
+}
+
+impl Evolution {
+    /// Starts a session with a first generation drawn from the preset
+    /// generators.
+    pub fn new(options: EvolveOptions, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut preset = SynthPreset::with_seed(rng.random());
+        let candidates = (0..options.population)
+            .map(|i| Candidate {
+                params: preset.generate(GENERATORS[i % GENERATORS.len()]),
+                fitness: 0.0,
+            })
+            .collect();
+
+        Evolution {
+            options,
+            candidates,
+            generation: 0,
+            rng,
+        }
+    }
+
+    /// Starts a session from mutated copies of a single sound.
+    pub fn from_params(options: EvolveOptions, seed: u64, params: SynthParams) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let candidates = (0..options.population)
+            .map(|i| {
+                let mut params = params;
+                if i > 0 {
+                    params.mutate_with(&mut rng, options.mutation_rate, options.mutation_strength);
+                    params.clamp_ranges();
+                }
+                Candidate {
+                    params,
+                    fitness: 0.0,
+                }
+            })
+            .collect();
+
+        Evolution {
+            options,
+            candidates,
+            generation: 0,
+            rng,
+        }
+    }
+
+    /// Number of generations bred so far.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Sets the fitness of one candidate.
+    pub fn rate(&mut self, index: usize, fitness: f32) {
+        if let Some(candidate) = self.candidates.get_mut(index) {
+            candidate.fitness = fitness;
+        }
+    }
+
+    /// Scores every candidate with a fitness function.
+    pub fn score_with(&mut self, mut fitness: impl FnMut(&SynthParams) -> f32) {
+        for candidate in &mut self.candidates {
+            candidate.fitness = fitness(&candidate.params);
+        }
+    }
+
+    /// Replaces the population with the next generation. The elite is
+    /// kept as is, the other children come from crossover of parents
+    /// picked in proportion to their fitness, followed by mutation.
+    pub fn next_generation(&mut self) {
+        let mut parents = std::mem::take(&mut self.candidates);
+        parents.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+
+        let elite = self.options.elitism.min(parents.len());
+        let mut children: Vec<Candidate> = parents[..elite]
+            .iter()
+            .map(|parent| Candidate {
+                params: parent.params,
+                fitness: 0.0,
+            })
+            .collect();
+
+        while !parents.is_empty() && children.len() < self.options.population {
+            let a = self.pick_parent(&parents);
+            let b = self.pick_parent(&parents);
+            let mut params = SynthParams::crossover(&a, &b, &mut self.rng);
+            params.mutate_with(
+                &mut self.rng,
+                self.options.mutation_rate,
+                self.options.mutation_strength,
+            );
+            params.clamp_ranges();
+            children.push(Candidate {
+                params,
+                fitness: 0.0,
+            });
+        }
+
+        self.candidates = children;
+        self.generation += 1;
+    }
+
+    fn pick_parent(&mut self, parents: &[Candidate]) -> SynthParams {
+        // a small floor keeps unrated candidates in the running
+        parents
+            .choose_weighted(&mut self.rng, |parent| parent.fitness.max(0.0) + 0.01)
+            .map(|parent| parent.params)
+            .unwrap_or(parents[0].params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS: EvolveOptions = EvolveOptions {
+        population: 9,
+        mutation_rate: 1.0,
+        mutation_strength: 1.0,
+        elitism: 1,
+    };
+
+    /// Plays `generations` generations scored by the base frequency.
+    fn run(mut evolution: Evolution, generations: usize) -> Evolution {
+        for _ in 0..generations {
+            evolution.score_with(|params| params.base_freq);
+            evolution.next_generation();
+        }
+        evolution
+    }
+
+    fn params(evolution: &Evolution) -> Vec<SynthParams> {
+        evolution.candidates.iter().map(|c| c.params).collect()
+    }
+
+    #[test]
+    fn same_seed_same_generations() {
+        let a = run(Evolution::new(OPTIONS, 42), 5);
+        let b = run(Evolution::new(OPTIONS, 42), 5);
+        assert_eq!(a.generation(), 5);
+        assert_eq!(params(&a), params(&b));
+
+        let c = run(Evolution::new(OPTIONS, 43), 5);
+        assert_ne!(params(&a), params(&c));
+    }
+
+    #[test]
+    fn candidates_stay_in_range() {
+        let sessions = [
+            Evolution::new(OPTIONS, 7),
+            Evolution::from_params(OPTIONS, 7, SynthParams::default()),
+        ];
+        for evolution in sessions {
+            let evolution = run(evolution, 30);
+            for params in params(&evolution) {
+                let mut clamped = params;
+                clamped.clamp_ranges();
+                assert_eq!(clamped, params);
+            }
+        }
+    }
+}
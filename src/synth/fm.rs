@@ -0,0 +1,76 @@
+//! Two-operator FM and ring modulation.
+//!
+//! Both run a sine modulator at a multiple of the carrier frequency, so
+//! it follows the slides, the pitch envelope, arpeggios and vibrato.
+//! Integer ratios give harmonic tones, others give the inharmonic spectra
+//! of bells and metallic hits.
+
+use serde::{Deserialize, Serialize};
+
+/// Highest modulator to carrier frequency ratio.
+pub const MAX_RATIO: f32 = 16.0;
+/// Highest modulation index.
+pub const MAX_INDEX: f32 = 20.0;
+
+/// Settings of the `Fm` wave type, a sine carrier whose phase is
+/// modulated by a sine.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FmParams {
+    /// Modulator frequency as a multiple of the carrier frequency.
+    pub ratio: f32,
+    /// Starting modulation depth in radians, 0 gives a pure sine and
+    /// higher values add brighter partials.
+    pub index: f32,
+    /// Index change per second, negative values make the tone duller as
+    /// it fades, like a struck bell.
+    pub index_sweep: f32,
+}
+
+impl Default for FmParams {
+    fn default() -> Self {
+        FmParams {
+            ratio: 2.0,
+            index: 2.0,
+            index_sweep: 0.0,
+        }
+    }
+}
+
+impl FmParams {
+    /// Brings every setting back into the range of its GUI slider.
+    pub fn clamp_ranges(&mut self) {
+        self.ratio = self.ratio.clamp(0.0, MAX_RATIO);
+        self.index = self.index.clamp(0.0, MAX_INDEX);
+        self.index_sweep = self.index_sweep.clamp(-MAX_INDEX, MAX_INDEX);
+    }
+}
+
+/// Multiplies the waveform by a sine, adding the sum and difference of
+/// their frequencies.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RingModParams {
+    /// Frequency of the second oscillator as a multiple of the carrier
+    /// frequency.
+    pub ratio: f32,
+    /// Wet/dry balance, 1 keeps the product only.
+    pub mix: f32,
+}
+
+impl Default for RingModParams {
+    fn default() -> Self {
+        RingModParams {
+            ratio: 1.5,
+            mix: 1.0,
+        }
+    }
+}
+
+impl RingModParams {
+    /// Brings every setting back into the range of its GUI slider.
+    pub fn clamp_ranges(&mut self) {
+        self.ratio = self.ratio.clamp(0.0, MAX_RATIO);
+        self.mix = self.mix.clamp(0.0, 1.0);
+    }
+}
@@ -0,0 +1,171 @@
+//! Low frequency oscillators routed to synth parameters.
+//!
+//! Each LFO has a shape, a rate, a depth and a single destination. The
+//! LFOs of a sound are combined into a `Modulation` once per output
+//! sample, then `synth_sample` applies it on top of the regular sweeps.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::SAMPLE_RATE;
+
+/// Number of LFOs stored in `SynthParams`.
+pub const LFO_COUNT: usize = 2;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    Saw,
+    SampleAndHold,
+}
+
+impl LfoShape {
+    pub const ALL: [LfoShape; 5] = [
+        LfoShape::Sine,
+        LfoShape::Triangle,
+        LfoShape::Square,
+        LfoShape::Saw,
+        LfoShape::SampleAndHold,
+    ];
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LfoTarget {
+    /// The LFO is switched off.
+    #[default]
+    None,
+    /// Up to one octave up and down.
+    Pitch,
+    /// Square wave duty cycle.
+    Duty,
+    /// Low-pass cutoff, closing a fully open filter.
+    LpfCutoff,
+    /// High-pass cutoff.
+    HpfCutoff,
+    /// Phaser offset.
+    PhaserOffset,
+    /// Tremolo, the volume dips by up to the depth.
+    Volume,
+}
+
+impl LfoTarget {
+    pub const ALL: [LfoTarget; 7] = [
+        LfoTarget::None,
+        LfoTarget::Pitch,
+        LfoTarget::Duty,
+        LfoTarget::LpfCutoff,
+        LfoTarget::HpfCutoff,
+        LfoTarget::PhaserOffset,
+        LfoTarget::Volume,
+    ];
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lfo {
+    pub shape: LfoShape,
+    pub target: LfoTarget,
+    /// Speed of the LFO, 0..1 maps to about 0.1Hz to 40Hz.
+    pub rate: f32,
+    /// Amount of modulation, 0..1.
+    pub depth: f32,
+}
+
+impl Lfo {
+    /// Frequency of the LFO in Hz.
+    pub fn frequency(&self) -> f32 {
+        0.1 + self.rate.powf(2.0) * 40.0
+    }
+}
+
+/// Running phase of an LFO.
+#[derive(Default, Clone, Copy)]
+pub struct LfoState {
+    phase: f32,
+    held: f32,
+}
+
+/// Combined effect of every LFO for one output sample. The neutral
+/// value of each field leaves the sound untouched.
+pub struct Modulation {
+    /// Multiplier of the oscillator period.
+    pub period: f32,
+    /// Offset added to the square duty.
+    pub duty: f32,
+    /// Multiplier of the low-pass cutoff.
+    pub lpf: f32,
+    /// Multiplier of the high-pass cutoff.
+    pub hpf: f32,
+    /// Offset added to the phaser delay, in samples.
+    pub phaser: f32,
+    /// Multiplier of the output volume.
+    pub volume: f32,
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Self {
+            period: 1.0,
+            duty: 0.0,
+            lpf: 1.0,
+            hpf: 1.0,
+            phaser: 0.0,
+            volume: 1.0,
+        }
+    }
+}
+
+/// Advances the LFOs by one sample and returns their combined effect.
+pub fn step(
+    lfos: &[Lfo; LFO_COUNT],
+    states: &mut [LfoState; LFO_COUNT],
+    rng: &mut impl Rng,
+) -> Modulation {
+    let mut modulation = Modulation::default();
+
+    for (lfo, state) in lfos.iter().zip(states.iter_mut()) {
+        if matches!(lfo.target, LfoTarget::None) {
+            continue;
+        }
+
+        // a reset LFO draws its first held value right away
+        if state.phase == 0.0 {
+            state.held = rng.random::<f32>() * 2.0 - 1.0;
+        }
+        state.phase += lfo.frequency() / SAMPLE_RATE as f32;
+        if state.phase >= 1.0 {
+            state.phase -= 1.0;
+            state.held = rng.random::<f32>() * 2.0 - 1.0;
+        }
+
+        let phase = state.phase;
+        let value = match lfo.shape {
+            LfoShape::Sine => (phase * std::f32::consts::TAU).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::Saw => 1.0 - phase * 2.0,
+            LfoShape::SampleAndHold => state.held,
+        } * lfo.depth;
+
+        match lfo.target {
+            LfoTarget::None => {}
+            LfoTarget::Pitch => modulation.period *= (-value).exp2(),
+            LfoTarget::Duty => modulation.duty += value * 0.25,
+            LfoTarget::LpfCutoff => modulation.lpf *= (value * 2.0).exp2(),
+            LfoTarget::HpfCutoff => modulation.hpf *= (value * 2.0).exp2(),
+            LfoTarget::PhaserOffset => modulation.phaser += value * 512.0,
+            LfoTarget::Volume => modulation.volume *= 1.0 - lfo.depth * 0.5 + value * 0.5,
+        }
+    }
+
+    modulation
+}
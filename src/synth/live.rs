@@ -0,0 +1,45 @@
+//! Parameters that can change while a sound is playing.
+
+/// Parameters a playing `Synth` follows without restarting the sound,
+/// see `Synth::set_live`. Every other field of `SynthParams` only takes
+/// effect the next time the sound is played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiveParam {
+    /// `SynthParams::base_freq`, slides keep going from the new pitch.
+    BaseFreq,
+    /// `SynthParams::lpf_freq`, the cutoff sweep keeps going. Sounds
+    /// whose `filter` replaces the low-pass filter ignore it, the
+    /// state-variable cutoff only changes on the next play.
+    LpfFreq,
+    /// `SynthParams::hpf_freq`, the cutoff sweep keeps going. Ignored
+    /// like `LpfFreq` when `filter` is set.
+    HpfFreq,
+    /// `SynthParams::vib_strength`.
+    VibStrength,
+    /// `SynthParams::vib_speed`.
+    VibSpeed,
+    /// Output volume of the sound, 0.5 by default.
+    Volume,
+}
+
+impl LiveParam {
+    pub const ALL: [LiveParam; 6] = [
+        LiveParam::BaseFreq,
+        LiveParam::LpfFreq,
+        LiveParam::HpfFreq,
+        LiveParam::VibStrength,
+        LiveParam::VibSpeed,
+        LiveParam::Volume,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveParam::BaseFreq => "base_freq",
+            LiveParam::LpfFreq => "lpf_freq",
+            LiveParam::HpfFreq => "hpf_freq",
+            LiveParam::VibStrength => "vib_strength",
+            LiveParam::VibSpeed => "vib_speed",
+            LiveParam::Volume => "volume",
+        }
+    }
+}
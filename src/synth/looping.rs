@@ -0,0 +1,90 @@
+//! Loop regions for seamlessly repeating sounds such as alarms,
+//! hums and engines.
+
+use std::ops::Range;
+
+/// Shortest loop that `find_loop` will return, in samples.
+const MIN_LOOP_LENGTH: usize = 256;
+
+/// A section of a rendered sound that can be repeated without clicks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopRegion {
+    /// First sample of the loop.
+    pub start: usize,
+    /// One past the last sample of the loop.
+    pub end: usize,
+}
+
+impl LoopRegion {
+    /// Creates a loop from user-specified points, `None` if the region
+    /// is empty.
+    pub fn new(start: usize, end: usize) -> Option<Self> {
+        (start < end).then_some(Self { start, end })
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// Plays the sound up to the end of the loop, repeats the loop
+    /// `repeats` more times and then plays the rest of the sound.
+    pub fn unroll(&self, samples: &[f32], repeats: usize) -> Vec<f32> {
+        let end = self.end.min(samples.len());
+        let start = self.start.min(end);
+
+        let mut data = Vec::with_capacity(samples.len() + (end - start) * repeats);
+        data.extend_from_slice(&samples[..end]);
+        for _ in 0..repeats {
+            data.extend_from_slice(&samples[start..end]);
+        }
+        data.extend_from_slice(&samples[end..]);
+        data
+    }
+}
+
+/// Looks for a loop inside `range`, usually the sustain stage of the
+/// sound. Both ends sit on rising zero crossings, and the end is chosen
+/// so its slope matches the start as closely as possible.
+pub fn find_loop(samples: &[f32], range: Range<usize>) -> Option<LoopRegion> {
+    let end = range.end.min(samples.len());
+    let begin = range.start.max(1);
+    if begin >= end {
+        return None;
+    }
+
+    let crossings: Vec<usize> = (begin..end)
+        .filter(|&i| samples[i - 1] < 0.0 && samples[i] >= 0.0)
+        .collect();
+    let &start = crossings.first()?;
+    let start_slope = slope(samples, start);
+
+    // prefer long loops ending in the second half of the range
+    let middle = begin + (end - begin) / 2;
+    let candidates: Vec<usize> = crossings
+        .iter()
+        .copied()
+        .filter(|&i| i >= start + MIN_LOOP_LENGTH)
+        .collect();
+    let late: Vec<usize> = candidates
+        .iter()
+        .copied()
+        .filter(|&i| i >= middle)
+        .collect();
+    let candidates = if late.is_empty() { candidates } else { late };
+
+    let loop_end = candidates.into_iter().min_by(|&a, &b| {
+        let da = (slope(samples, a) - start_slope).abs();
+        let db = (slope(samples, b) - start_slope).abs();
+        da.total_cmp(&db)
+    })?;
+
+    LoopRegion::new(start, loop_end)
+}
+
+fn slope(samples: &[f32], index: usize) -> f32 {
+    samples[index] - samples[index - 1]
+}
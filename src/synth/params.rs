@@ -1,17 +1,57 @@
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Default, Clone, Copy)]
+use super::arpeggio::Arpeggio;
+use super::chip::Chip;
+use super::effects::{EffectParams, FilterParams};
+use super::envelope::Envelope;
+use super::fm::{FmParams, RingModParams};
+use super::lfo::{LFO_COUNT, Lfo};
+use super::pitch::{self, Note};
+use super::sample::SampleParams;
+use super::wavetable::Wavetable;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum WaveType {
     #[default]
     Square,
     Sawtooth,
     Sine,
     Noise,
+    /// The single-cycle waveform of `SynthParams::wavetable`.
+    Wavetable,
+    /// The recording set with `Synth::set_sample`.
+    Sample,
+    /// Two-operator FM set by `SynthParams::fm`.
+    Fm,
+}
+
+impl WaveType {
+    pub const ALL: [WaveType; 7] = [
+        WaveType::Square,
+        WaveType::Sawtooth,
+        WaveType::Sine,
+        WaveType::Noise,
+        WaveType::Wavetable,
+        WaveType::Sample,
+        WaveType::Fm,
+    ];
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SynthParams {
     pub wave_type: WaveType,
+    /// Waveform of the `Wavetable` wave type, a sine when unset.
+    pub wavetable: Option<Wavetable>,
+    /// Playback of the `Sample` wave type.
+    pub sample: SampleParams,
+    /// Operators of the `Fm` wave type.
+    pub fm: FmParams,
+    /// Ring modulation applied to every wave type when set.
+    pub ring_mod: Option<RingModParams>,
+    /// Restricts the sound to what the chip can play, see `Chip::snap`.
+    pub chip: Option<Chip>,
     pub base_freq: f32,
 
     pub freq_limit: f32,
@@ -27,12 +67,22 @@ pub struct SynthParams {
     pub env_sustain: f32,
     pub env_decay: f32,
     pub env_punch: f32,
+    pub env_hold: bool,
+    /// Replaces the classic attack/sustain/decay volume envelope.
+    pub volume_env: Option<Envelope>,
+    /// Pitch offset, a level of 1 is two octaves up.
+    pub pitch_env: Option<Envelope>,
+    /// Low-pass cutoff offset, a level of 1 is four octaves up.
+    pub filter_env: Option<Envelope>,
 
     pub lpf_resonance: f32,
     pub lpf_freq: f32,
     pub lpf_ramp: f32,
     pub hpf_freq: f32,
     pub hpf_ramp: f32,
+    /// State-variable filter replacing the low-pass and high-pass
+    /// filters above when set.
+    pub filter: Option<FilterParams>,
 
     pub pha_offset: f32,
     pub pha_ramp: f32,
@@ -40,12 +90,24 @@ pub struct SynthParams {
     pub repeat_speed: f32,
     pub arp_speed: f32,
     pub arp_mod: f32,
+    /// Semitone pattern replacing the `arp_mod` jump when set.
+    pub arpeggio: Option<Arpeggio>,
+
+    pub lfos: [Lfo; LFO_COUNT],
+
+    /// Distortion, chorus, delay and reverb applied after the envelope.
+    pub effects: EffectParams,
 }
 
 impl Default for SynthParams {
     fn default() -> Self {
         Self {
             wave_type: Default::default(),
+            wavetable: Default::default(),
+            sample: Default::default(),
+            fm: Default::default(),
+            ring_mod: Default::default(),
+            chip: Default::default(),
             base_freq: 0.3,
             freq_limit: Default::default(),
             freq_ramp: Default::default(),
@@ -60,12 +122,17 @@ impl Default for SynthParams {
             env_sustain: 0.3,
             env_decay: 0.4,
             env_punch: Default::default(),
+            env_hold: Default::default(),
+            volume_env: Default::default(),
+            pitch_env: Default::default(),
+            filter_env: Default::default(),
 
             lpf_resonance: Default::default(),
             lpf_freq: 1.0,
             lpf_ramp: Default::default(),
             hpf_freq: Default::default(),
             hpf_ramp: Default::default(),
+            filter: Default::default(),
 
             pha_offset: Default::default(),
             pha_ramp: Default::default(),
@@ -73,6 +140,11 @@ impl Default for SynthParams {
             repeat_speed: Default::default(),
             arp_speed: Default::default(),
             arp_mod: Default::default(),
+            arpeggio: Default::default(),
+
+            lfos: Default::default(),
+
+            effects: Default::default(),
         }
     }
 }
@@ -82,6 +154,126 @@ impl SynthParams {
         Self::default()
     }
 
+    /// Starting pitch of the sound in Hz when played at `sample_rate`.
+    pub fn pitch_hz(&self, sample_rate: u32) -> f32 {
+        pitch::param_to_hz(self.base_freq, sample_rate)
+    }
+
+    /// Nearest note of the starting pitch.
+    pub fn note(&self, sample_rate: u32) -> Note {
+        Note::from_hz(self.pitch_hz(sample_rate))
+    }
+
+    /// Pitch at which a downward slide stops the sound, `None` when
+    /// there is no limit.
+    pub fn min_pitch_hz(&self, sample_rate: u32) -> Option<f32> {
+        (self.freq_limit > 0.0).then(|| pitch::param_to_hz(self.freq_limit, sample_rate))
+    }
+
+    /// Returns the sound tuned to start at `hz` when played at
+    /// `sample_rate`. The frequency limit moves by the same interval so
+    /// slides keep their range.
+    pub fn with_pitch_hz(mut self, hz: f32, sample_rate: u32) -> Self {
+        if let Some(limit) = self.min_pitch_hz(sample_rate) {
+            let ratio = hz / self.pitch_hz(sample_rate);
+            self.freq_limit = pitch::hz_to_param(limit * ratio, sample_rate);
+        }
+        self.base_freq = pitch::hz_to_param(hz, sample_rate);
+        self
+    }
+
+    /// Returns the sound tuned to start on `note`, see `with_pitch_hz`.
+    pub fn with_note(self, note: Note, sample_rate: u32) -> Self {
+        self.with_pitch_hz(note.hz(), sample_rate)
+    }
+
+    /// Interpolates between two sounds, `t` = 0 gives `a` and `t` = 1
+    /// gives `b`. Settings that can't be blended, such as the wave type,
+    /// are taken from the nearest of the two.
+    pub fn lerp(a: &SynthParams, b: &SynthParams, t: f32) -> SynthParams {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |x: f32, y: f32| x + (y - x) * t;
+        let nearest = if t < 0.5 { a } else { b };
+
+        SynthParams {
+            wave_type: nearest.wave_type,
+            wavetable: match (&a.wavetable, &b.wavetable) {
+                (Some(a), Some(b)) => Some(Wavetable::lerp(a, b, t)),
+                _ => nearest.wavetable,
+            },
+            sample: SampleParams {
+                mode: nearest.sample.mode,
+                root: mix(a.sample.root, b.sample.root),
+            },
+            fm: FmParams {
+                ratio: mix(a.fm.ratio, b.fm.ratio),
+                index: mix(a.fm.index, b.fm.index),
+                index_sweep: mix(a.fm.index_sweep, b.fm.index_sweep),
+            },
+            ring_mod: match (&a.ring_mod, &b.ring_mod) {
+                (Some(a), Some(b)) => Some(RingModParams {
+                    ratio: mix(a.ratio, b.ratio),
+                    mix: mix(a.mix, b.mix),
+                }),
+                _ => nearest.ring_mod,
+            },
+            chip: nearest.chip,
+            base_freq: mix(a.base_freq, b.base_freq),
+            freq_limit: mix(a.freq_limit, b.freq_limit),
+            freq_ramp: mix(a.freq_ramp, b.freq_ramp),
+            freq_dramp: mix(a.freq_dramp, b.freq_dramp),
+            duty: mix(a.duty, b.duty),
+            duty_ramp: mix(a.duty_ramp, b.duty_ramp),
+
+            vib_strength: mix(a.vib_strength, b.vib_strength),
+            vib_speed: mix(a.vib_speed, b.vib_speed),
+
+            env_attack: mix(a.env_attack, b.env_attack),
+            env_sustain: mix(a.env_sustain, b.env_sustain),
+            env_decay: mix(a.env_decay, b.env_decay),
+            env_punch: mix(a.env_punch, b.env_punch),
+            env_hold: nearest.env_hold,
+            volume_env: nearest.volume_env,
+            pitch_env: nearest.pitch_env,
+            filter_env: nearest.filter_env,
+
+            lpf_resonance: mix(a.lpf_resonance, b.lpf_resonance),
+            lpf_freq: mix(a.lpf_freq, b.lpf_freq),
+            lpf_ramp: mix(a.lpf_ramp, b.lpf_ramp),
+            hpf_freq: mix(a.hpf_freq, b.hpf_freq),
+            hpf_ramp: mix(a.hpf_ramp, b.hpf_ramp),
+            filter: nearest.filter,
+
+            pha_offset: mix(a.pha_offset, b.pha_offset),
+            pha_ramp: mix(a.pha_ramp, b.pha_ramp),
+
+            repeat_speed: mix(a.repeat_speed, b.repeat_speed),
+            arp_speed: mix(a.arp_speed, b.arp_speed),
+            arp_mod: mix(a.arp_mod, b.arp_mod),
+            arpeggio: nearest.arpeggio,
+
+            lfos: std::array::from_fn(|i| Lfo {
+                shape: nearest.lfos[i].shape,
+                target: nearest.lfos[i].target,
+                rate: mix(a.lfos[i].rate, b.lfos[i].rate),
+                depth: mix(a.lfos[i].depth, b.lfos[i].depth),
+            }),
+
+            effects: EffectParams::lerp(&a.effects, &b.effects, t),
+        }
+    }
+
+    /// Returns `steps` sounds evenly spaced from `a` to `b`, both included.
+    pub fn morph(a: &SynthParams, b: &SynthParams, steps: usize) -> Vec<SynthParams> {
+        match steps {
+            0 => Vec::new(),
+            1 => vec![*a],
+            _ => (0..steps)
+                .map(|i| SynthParams::lerp(a, b, i as f32 / (steps - 1) as f32))
+                .collect(),
+        }
+    }
+
     pub fn mutate(&mut self, rng: &mut impl Rng) {
         if rng.random::<bool>() {
             self.base_freq += frnd(rng, 0.1) - 0.05;
@@ -149,6 +341,179 @@ impl SynthParams {
     }
 }
 
+impl SynthParams {
+    /// Mutates each continuous parameter with probability `rate` by a
+    /// random step of at most `strength` in either direction.
+    pub fn mutate_with(&mut self, rng: &mut impl Rng, rate: f32, strength: f32) {
+        for value in self.values_mut() {
+            if rng.random::<f32>() < rate {
+                *value += (rng.random::<f32>() * 2.0 - 1.0) * strength;
+            }
+        }
+    }
+
+    /// Builds a child taking every parameter from one of the two
+    /// parents, chosen at random.
+    pub fn crossover(a: &SynthParams, b: &SynthParams, rng: &mut impl Rng) -> SynthParams {
+        let mut child = *a;
+        if rng.random::<bool>() {
+            child.wave_type = b.wave_type;
+        }
+        if rng.random::<bool>() {
+            child.wavetable = b.wavetable;
+        }
+        if rng.random::<bool>() {
+            child.sample = b.sample;
+        }
+        if rng.random::<bool>() {
+            child.fm = b.fm;
+        }
+        if rng.random::<bool>() {
+            child.ring_mod = b.ring_mod;
+        }
+        if rng.random::<bool>() {
+            child.chip = b.chip;
+        }
+        if rng.random::<bool>() {
+            child.env_hold = b.env_hold;
+        }
+        if rng.random::<bool>() {
+            child.volume_env = b.volume_env;
+        }
+        if rng.random::<bool>() {
+            child.pitch_env = b.pitch_env;
+        }
+        if rng.random::<bool>() {
+            child.filter_env = b.filter_env;
+        }
+        if rng.random::<bool>() {
+            child.filter = b.filter;
+        }
+        if rng.random::<bool>() {
+            child.arpeggio = b.arpeggio;
+        }
+        for (lfo, other) in child.lfos.iter_mut().zip(b.lfos) {
+            if rng.random::<bool>() {
+                *lfo = other;
+            }
+        }
+        if rng.random::<bool>() {
+            child.effects.distortion = b.effects.distortion;
+        }
+        if rng.random::<bool>() {
+            child.effects.chorus = b.effects.chorus;
+        }
+        if rng.random::<bool>() {
+            child.effects.delay = b.effects.delay;
+        }
+        if rng.random::<bool>() {
+            child.effects.reverb = b.effects.reverb;
+        }
+        for (value, other) in child.values_mut().into_iter().zip(b.values()) {
+            if rng.random::<bool>() {
+                *value = other;
+            }
+        }
+        child
+    }
+
+    /// Brings every parameter back into the range of its GUI slider.
+    pub fn clamp_ranges(&mut self) {
+        let unit = [
+            &mut self.base_freq,
+            &mut self.freq_limit,
+            &mut self.duty,
+            &mut self.vib_strength,
+            &mut self.vib_speed,
+            &mut self.env_attack,
+            &mut self.env_sustain,
+            &mut self.env_decay,
+            &mut self.env_punch,
+            &mut self.lpf_resonance,
+            &mut self.lpf_freq,
+            &mut self.hpf_freq,
+            &mut self.repeat_speed,
+            &mut self.arp_speed,
+        ];
+        let lfos = self
+            .lfos
+            .iter_mut()
+            .flat_map(|lfo| [&mut lfo.rate, &mut lfo.depth]);
+        for value in unit.into_iter().chain(lfos) {
+            *value = value.clamp(0.0, 1.0);
+        }
+
+        let signed = [
+            &mut self.freq_ramp,
+            &mut self.freq_dramp,
+            &mut self.duty_ramp,
+            &mut self.lpf_ramp,
+            &mut self.hpf_ramp,
+            &mut self.pha_offset,
+            &mut self.pha_ramp,
+            &mut self.arp_mod,
+        ];
+        for value in signed {
+            *value = value.clamp(-1.0, 1.0);
+        }
+
+        self.sample.root = self.sample.root.clamp(20.0, 20_000.0);
+        if let Some(wavetable) = &mut self.wavetable {
+            wavetable.clamp_ranges();
+        }
+        self.fm.clamp_ranges();
+        if let Some(ring_mod) = &mut self.ring_mod {
+            ring_mod.clamp_ranges();
+        }
+        if let Some(filter) = &mut self.filter {
+            filter.clamp_ranges();
+        }
+        self.effects.clamp_ranges();
+    }
+
+    /// Every continuous parameter, in declaration order.
+    fn values(&self) -> Vec<f32> {
+        let mut params = *self;
+        params
+            .values_mut()
+            .into_iter()
+            .map(|value| *value)
+            .collect()
+    }
+
+    fn values_mut(&mut self) -> Vec<&mut f32> {
+        let mut values = vec![
+            &mut self.base_freq,
+            &mut self.freq_limit,
+            &mut self.freq_ramp,
+            &mut self.freq_dramp,
+            &mut self.duty,
+            &mut self.duty_ramp,
+            &mut self.vib_strength,
+            &mut self.vib_speed,
+            &mut self.env_attack,
+            &mut self.env_sustain,
+            &mut self.env_decay,
+            &mut self.env_punch,
+            &mut self.lpf_resonance,
+            &mut self.lpf_freq,
+            &mut self.lpf_ramp,
+            &mut self.hpf_freq,
+            &mut self.hpf_ramp,
+            &mut self.pha_offset,
+            &mut self.pha_ramp,
+            &mut self.repeat_speed,
+            &mut self.arp_speed,
+            &mut self.arp_mod,
+        ];
+        for lfo in &mut self.lfos {
+            values.push(&mut lfo.rate);
+            values.push(&mut lfo.depth);
+        }
+        values
+    }
+}
+
 fn frnd(rng: &mut impl Rng, range: f32) -> f32 {
     rng.random::<f32>() * range
 }
@@ -0,0 +1,168 @@
+//! Conversions between the abstract frequency parameters and musical
+//! pitch.
+//!
+//! `base_freq` and `freq_limit` are 0..1 values that set the period of
+//! the oscillator, counted in supersampled steps. The period is
+//! truncated to a whole number of steps while playing, so the highest
+//! notes are slightly out of tune; the conversions here account for it.
+
+use super::SUPERSAMPLING_FACTOR;
+
+/// Frequency of MIDI note 69, the A above middle C.
+pub const A4_HZ: f32 = 440.0;
+
+/// Names of the pitch classes, starting from C.
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Oscillator period of a frequency parameter, in supersampled steps.
+pub fn period(value: f32) -> f32 {
+    100.0 / (value.powf(2.0) + 0.001)
+}
+
+/// Pitch played by a frequency parameter at the given sample rate.
+pub fn param_to_hz(value: f32, sample_rate: u32) -> f32 {
+    let steps = period(value).floor().max(8.0);
+    sample_rate as f32 * SUPERSAMPLING_FACTOR as f32 / steps
+}
+
+/// Frequency parameter closest to a pitch at the given sample rate,
+/// clamped to the 0..1 range.
+pub fn hz_to_param(hz: f32, sample_rate: u32) -> f32 {
+    if hz <= 0.0 {
+        return 0.0;
+    }
+
+    // aim at the middle of the nearest whole period so the truncation
+    // lands on it
+    let steps = (sample_rate as f32 * SUPERSAMPLING_FACTOR as f32 / hz).round();
+    let value = 100.0 / (steps + 0.5) - 0.001;
+    value.max(0.0).sqrt().min(1.0)
+}
+
+/// Fractional MIDI note number of a pitch.
+pub fn hz_to_midi(hz: f32) -> f32 {
+    69.0 + 12.0 * (hz / A4_HZ).log2()
+}
+
+/// Pitch of a fractional MIDI note number.
+pub fn midi_to_hz(note: f32) -> f32 {
+    A4_HZ * ((note - 69.0) / 12.0).exp2()
+}
+
+/// A MIDI note with a detune in cents.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    /// MIDI note number, 60 is middle C.
+    pub midi: i32,
+    /// Detune from the note, -50..50 for the nearest note.
+    pub cents: f32,
+}
+
+impl Note {
+    pub fn new(midi: i32, cents: f32) -> Self {
+        Note { midi, cents }
+    }
+
+    /// Nearest note of a pitch, with the remaining detune.
+    pub fn from_hz(hz: f32) -> Self {
+        let note = hz_to_midi(hz);
+        let midi = note.round();
+        Note {
+            midi: midi as i32,
+            cents: (note - midi) * 100.0,
+        }
+    }
+
+    pub fn hz(&self) -> f32 {
+        midi_to_hz(self.midi as f32 + self.cents / 100.0)
+    }
+
+    /// Parses a scientific pitch name such as "C4", "F#3" or "Bb5".
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        let letter = name.chars().next()?.to_ascii_uppercase();
+        let mut class = NOTE_NAMES.iter().position(|n| n.starts_with(letter))? as i32;
+
+        let mut rest = &name[1..];
+        if let Some(stripped) = rest.strip_prefix('#') {
+            class += 1;
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('b') {
+            class -= 1;
+            rest = stripped;
+        }
+
+        let octave: i32 = rest.parse().ok()?;
+        Some(Note::new((octave + 1) * 12 + class, 0.0))
+    }
+
+    /// Scientific pitch name such as "C#4".
+    pub fn name(&self) -> String {
+        let octave = self.midi.div_euclid(12) - 1;
+        format!(
+            "{}{}",
+            NOTE_NAMES[self.midi.rem_euclid(12) as usize],
+            octave
+        )
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scale {
+    #[default]
+    Chromatic,
+    Major,
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+}
+
+impl Scale {
+    pub const ALL: [Scale; 5] = [
+        Scale::Chromatic,
+        Scale::Major,
+        Scale::Minor,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+    ];
+
+    /// Semitones of the scale degrees above the root.
+    pub fn intervals(&self) -> &'static [i32] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+        }
+    }
+
+    /// Nearest note of the scale built on `root` (0 is C, 11 is B) to a
+    /// fractional MIDI note number.
+    pub fn snap(&self, root: i32, note: f32) -> i32 {
+        let nearest = note.round() as i32;
+        (nearest - 6..=nearest + 6)
+            .filter(|candidate| {
+                self.intervals()
+                    .contains(&(candidate - root).rem_euclid(12))
+            })
+            .min_by(|a, b| {
+                let da = (*a as f32 - note).abs();
+                let db = (*b as f32 - note).abs();
+                da.total_cmp(&db)
+            })
+            .unwrap_or(nearest)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Chromatic => "Chromatic",
+            Scale::Major => "Major",
+            Scale::Minor => "Minor",
+            Scale::MajorPentatonic => "Major pentatonic",
+            Scale::MinorPentatonic => "Minor pentatonic",
+        }
+    }
+}
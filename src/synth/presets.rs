@@ -1,7 +1,23 @@
+use super::arpeggio::Arpeggio;
+use super::chip::Chip;
+use super::fm::{FmParams, RingModParams};
+use super::lfo::{LfoShape, LfoTarget};
 use super::params::{SynthParams, WaveType};
 
 use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
 
+/// Semitone patterns used by the jingle-like presets.
+const ARPEGGIOS: &[&[f32]] = &[
+    &[0.0, 5.0],
+    &[0.0, 7.0, 12.0],
+    &[0.0, 4.0, 7.0],
+    &[0.0, 4.0, 7.0, 12.0],
+    &[0.0, 3.0, 7.0, 12.0],
+];
+
+/// Inharmonic modulator ratios of bells and metal.
+const METALLIC_RATIOS: &[f32] = &[1.4, 2.76, 3.5, 5.19, 7.07];
+
 #[derive(Default, Copy, Clone)]
 pub enum SoundType {
     #[default]
@@ -12,6 +28,7 @@ pub enum SoundType {
     HitHurt,
     Jump,
     BlipSelect,
+    Bell,
     Randomize,
 }
 
@@ -31,6 +48,7 @@ impl TryFrom<&str> for SoundType {
             "jump" => Ok(Self::Jump),
             "select" => Ok(Self::BlipSelect),
             "blip" => Ok(Self::BlipSelect),
+            "bell" => Ok(Self::Bell),
             "randomize" => Ok(Self::Randomize),
             _ => Err(format!("Unknown sound type: {}", value)),
         }
@@ -39,6 +57,8 @@ impl TryFrom<&str> for SoundType {
 
 pub struct SynthPreset {
     rng: StdRng,
+    /// Hardware the generated sounds are snapped to.
+    chip: Option<Chip>,
 }
 
 impl Default for SynthPreset {
@@ -51,11 +71,36 @@ impl SynthPreset {
     pub fn new() -> Self {
         SynthPreset {
             rng: StdRng::from_os_rng(),
+            chip: None,
+        }
+    }
+
+    /// Creates a generator that always produces the same sequence of
+    /// sounds for the same seed.
+    pub fn with_seed(seed: u64) -> Self {
+        SynthPreset {
+            rng: StdRng::seed_from_u64(seed),
+            chip: None,
         }
     }
 
+    /// Makes the generator produce sounds the chip can play, see
+    /// `Chip::snap`.
+    pub fn with_chip(mut self, chip: Chip) -> Self {
+        self.chip = Some(chip);
+        self
+    }
+
+    pub fn set_chip(&mut self, chip: Option<Chip>) {
+        self.chip = chip;
+    }
+
+    pub fn chip(&self) -> Option<Chip> {
+        self.chip
+    }
+
     pub fn generate(&mut self, sound_type: SoundType) -> SynthParams {
-        match sound_type {
+        let params = match sound_type {
             SoundType::PickupCoin => self.coin(),
             SoundType::LaserShoot => self.shoot(),
             SoundType::Explosion => self.explosion(),
@@ -63,7 +108,12 @@ impl SynthPreset {
             SoundType::HitHurt => self.hit(),
             SoundType::Jump => self.jump(),
             SoundType::BlipSelect => self.blip(),
+            SoundType::Bell => self.bell(),
             SoundType::Randomize => self.randomize(),
+        };
+        match self.chip {
+            Some(chip) => chip.snap(&params),
+            None => params,
         }
     }
 
@@ -78,6 +128,9 @@ impl SynthPreset {
         if self.rng.random_bool(0.5) {
             params.arp_speed = 0.5 + self.frnd(0.2);
             params.arp_mod = 0.2 + self.frnd(0.4);
+        } else if self.rng.random_ratio(1, 3) {
+            let steps = *ARPEGGIOS.choose(&mut self.rng).unwrap();
+            params.arpeggio = Some(Arpeggio::new(steps, 0.04 + self.frnd(0.04), false));
         }
         params
     }
@@ -204,6 +257,11 @@ impl SynthPreset {
             }
         }
 
+        if self.rng.random_ratio(1, 3) {
+            let steps = *ARPEGGIOS[2..].choose(&mut self.rng).unwrap();
+            params.arpeggio = Some(Arpeggio::new(steps, 0.05 + self.frnd(0.05), true));
+        }
+
         params
     }
 
@@ -225,6 +283,16 @@ impl SynthPreset {
             params.hpf_freq = self.frnd(0.3);
         }
 
+        // metallic clank
+        if self.rng.random_ratio(1, 4) {
+            params.wave_type = WaveType::Fm;
+            params.fm = FmParams {
+                ratio: *METALLIC_RATIOS.choose(&mut self.rng).unwrap(),
+                index: 3.0 + self.frnd(5.0),
+                index_sweep: -10.0 - self.frnd(20.0),
+            };
+        }
+
         params
     }
 
@@ -263,6 +331,43 @@ impl SynthPreset {
             params.duty = self.frnd(0.6);
         }
 
+        // alien beep
+        if self.rng.random_ratio(1, 4) {
+            params.ring_mod = Some(RingModParams {
+                ratio: 0.5 + self.frnd(3.0),
+                mix: 0.5 + self.frnd(0.5),
+            });
+        }
+
+        params
+    }
+
+    fn bell(&mut self) -> SynthParams {
+        let mut params = SynthParams::new();
+        params.wave_type = WaveType::Fm;
+        params.fm = FmParams {
+            ratio: *METALLIC_RATIOS.choose(&mut self.rng).unwrap(),
+            index: 2.0 + self.frnd(4.0),
+            index_sweep: -1.0 - self.frnd(4.0),
+        };
+        params.base_freq = 0.3 + self.frnd(0.4);
+        params.env_attack = 0.0;
+        params.env_sustain = self.frnd(0.1);
+        params.env_decay = 0.4 + self.frnd(0.4);
+        params.env_punch = self.frnd(0.3);
+
+        if self.rng.random_ratio(1, 3) {
+            params.vib_strength = self.frnd(0.1);
+            params.vib_speed = 0.3 + self.frnd(0.3);
+        }
+
+        if self.rng.random_ratio(1, 4) {
+            params.ring_mod = Some(RingModParams {
+                ratio: *METALLIC_RATIOS.choose(&mut self.rng).unwrap(),
+                mix: self.frnd(0.5),
+            });
+        }
+
         params
     }
 
@@ -326,6 +431,20 @@ impl SynthPreset {
         params.arp_speed = self.frnd(2.0) - 1.0;
         params.arp_mod = self.frnd(2.0) - 1.0;
 
+        // most sounds get no LFO, pitch and volume wobbles stay shallow
+        for lfo in &mut params.lfos {
+            if !self.rng.random_ratio(1, 3) {
+                continue;
+            }
+            lfo.shape = *LfoShape::ALL.choose(&mut self.rng).unwrap();
+            lfo.target = *LfoTarget::ALL[1..].choose(&mut self.rng).unwrap();
+            lfo.rate = self.frnd(1.0).powf(2.0);
+            lfo.depth = match lfo.target {
+                LfoTarget::Pitch | LfoTarget::Volume => self.frnd(0.3).powf(2.0),
+                _ => self.frnd(1.0).powf(2.0),
+            };
+        }
+
         params
     }
 
@@ -0,0 +1,106 @@
+//! Recorded samples played by the sample oscillator.
+//!
+//! A sample is too large to live in `SynthParams`, so it is loaded once
+//! and handed to the `Synth` next to the params, see `Synth::set_sample`.
+//! The params only keep how it plays: looped or once, and the pitch at
+//! which it plays at its recorded speed. Slides, arpeggios and vibrato
+//! change the playback speed like they change the pitch of the other
+//! waveforms.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::import::wav;
+use crate::synth::pitch::Note;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SampleMode {
+    /// Plays the sample once, then stays silent.
+    #[default]
+    OneShot,
+    /// Starts over at the end of the sample.
+    Loop,
+}
+
+impl SampleMode {
+    pub const ALL: [SampleMode; 2] = [SampleMode::OneShot, SampleMode::Loop];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SampleParams {
+    pub mode: SampleMode,
+    /// Pitch in Hz at which the sample plays at its recorded speed.
+    pub root: f32,
+}
+
+impl Default for SampleParams {
+    fn default() -> Self {
+        SampleParams {
+            mode: SampleMode::OneShot,
+            root: Note::new(60, 0.0).hz(),
+        }
+    }
+}
+
+/// A mono recording.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sample {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl Sample {
+    pub fn new(samples: Vec<f32>, sample_rate: u32) -> Self {
+        Sample {
+            samples,
+            sample_rate,
+        }
+    }
+
+    /// Reads a WAV file, mixed down to mono.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let (samples, sample_rate) = wav::load(path)?;
+        if samples.is_empty() {
+            anyhow::bail!("Empty sample");
+        }
+        Ok(Sample::new(samples, sample_rate))
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Length in seconds at the recorded speed.
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate.max(1) as f32
+    }
+
+    /// Value at the fractional `position`, in samples from the start.
+    pub fn read(&self, position: f64, mode: SampleMode) -> f32 {
+        let len = self.samples.len();
+        if len == 0 {
+            return 0.0;
+        }
+
+        let position = match mode {
+            SampleMode::OneShot if position >= len as f64 => return 0.0,
+            SampleMode::OneShot => position,
+            SampleMode::Loop => position % len as f64,
+        };
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+        let a = self.samples[index];
+        let b = match self.samples.get(index + 1) {
+            Some(&next) => next,
+            None if mode == SampleMode::Loop => self.samples[0],
+            None => 0.0,
+        };
+        a + (b - a) * fraction
+    }
+}
@@ -1,3 +1,7 @@
+use super::Voice;
+use super::lfo::{LFO_COUNT, LfoState};
+
+#[derive(Default)]
 pub struct SynthState {
     pub playing_sample: bool,
     pub phase: i32,
@@ -7,6 +11,7 @@ pub struct SynthState {
     pub arp_time: i32,
     pub arp_limit: i32,
     pub arp_mod: f32,
+    pub arp_step: usize,
 
     pub fperiod: f32,
     pub period: i32,
@@ -17,71 +22,45 @@ pub struct SynthState {
     pub square_duty: f32,
     pub square_slide: f32,
 
-    pub fltp: f32,
-    pub fltdp: f32,
-    pub fltw: f32,
-    pub fltw_d: f32,
-    pub fltdmp: f32,
-    pub fltphp: f32,
-    pub flthp: f32,
-    pub flthp_d: f32,
-
     pub vib_phase: f32,
     pub vib_speed: f32,
     pub vib_amp: f32,
 
+    pub env_clock: usize,
+    /// Clock of the custom volume envelope, which stops at its sustain
+    /// point while a held sound waits for release.
+    pub volume_clock: usize,
     pub env_vol: f32,
     pub env_stage: i32,
     pub env_time: i32,
     pub env_length: [i32; 3],
+    pub released: bool,
+    pub release_vol: f32,
+
+    /// Samples left of the post effect tails once the sound ends.
+    pub tail_time: usize,
+
+    /// Whether the sound restarted at least once, see `repeat_speed`.
+    pub repeated: bool,
 
-    pub fphase: f32,
-    pub fdphase: f32,
-    pub iphase: i32,
-    pub ipp: i32,
-    pub phaser_buffer: [f32; 1024],
+    /// Read position in the recording of the `Sample` wave type.
+    pub sample_pos: f64,
+
+    /// Modulator phase of the `Fm` wave type, 0..1.
+    pub fm_phase: f32,
+    /// Modulation index, index sweep included.
+    pub fm_index: f32,
+    /// Phase of the ring modulation oscillator, 0..1.
+    pub ring_phase: f32,
+
+    pub voice: Voice,
+
+    /// Noise shift register in the chip modes.
+    pub lfsr: u16,
+    /// Fraction of an LFSR clock elapsed.
+    pub lfsr_clock: f32,
 
     pub noise_buffer: [f32; 32],
-}
 
-impl Default for SynthState {
-    fn default() -> Self {
-        Self {
-            playing_sample: Default::default(),
-            phase: Default::default(),
-            rep_time: Default::default(),
-            rep_limit: Default::default(),
-            arp_time: Default::default(),
-            arp_limit: Default::default(),
-            arp_mod: Default::default(),
-            fperiod: Default::default(),
-            period: Default::default(),
-            fmaxperiod: Default::default(),
-            fslide: Default::default(),
-            fdslide: Default::default(),
-            square_duty: Default::default(),
-            square_slide: Default::default(),
-            fltp: Default::default(),
-            fltdp: Default::default(),
-            fltw: Default::default(),
-            fltw_d: Default::default(),
-            fltdmp: Default::default(),
-            fltphp: Default::default(),
-            flthp: Default::default(),
-            flthp_d: Default::default(),
-            vib_phase: Default::default(),
-            vib_speed: Default::default(),
-            vib_amp: Default::default(),
-            env_vol: Default::default(),
-            env_stage: Default::default(),
-            env_time: Default::default(),
-            env_length: Default::default(),
-            fphase: Default::default(),
-            fdphase: Default::default(),
-            iphase: Default::default(),
-            ipp: Default::default(),
-            phaser_buffer: [0.0; 1024],
-            noise_buffer: Default::default(),
-        }
-    }
+    pub lfos: [LfoState; LFO_COUNT],
 }
@@ -0,0 +1,239 @@
+//! Single-cycle waveforms played by the wavetable oscillator.
+//!
+//! A wavetable holds one period of a waveform as 32 to 256 points,
+//! inside `SynthParams`. The oscillator reads it with linear
+//! interpolation, so short tables drawn by hand sound smooth. Cycles
+//! imported from a WAV can be longer: the params keep a 256-point copy
+//! and the full `Cycle` is handed to the `Synth` next to them, see
+//! `Synth::set_cycle`, so they keep their detail.
+
+use std::f32::consts::TAU;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::import::wav;
+
+/// Fewest points a wavetable holds.
+pub const MIN_POINTS: usize = 32;
+/// Most points a wavetable holds. Every `SynthParams` stores this
+/// many, so it stays small.
+pub const MAX_POINTS: usize = 256;
+/// Most points of an imported `Cycle`.
+pub const MAX_CYCLE_POINTS: usize = 2048;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<f32>", into = "Vec<f32>")]
+pub struct Wavetable {
+    points: [f32; MAX_POINTS],
+    len: usize,
+}
+
+impl Default for Wavetable {
+    fn default() -> Self {
+        Wavetable::sine(64)
+    }
+}
+
+impl Wavetable {
+    /// Creates a wavetable from one period of samples. Tables shorter
+    /// than `MIN_POINTS` or longer than `MAX_POINTS` are resampled to
+    /// fit.
+    pub fn new(samples: &[f32]) -> Self {
+        let len = samples.len().clamp(MIN_POINTS, MAX_POINTS);
+        let mut table = Wavetable {
+            points: [0.0; MAX_POINTS],
+            len,
+        };
+        if !samples.is_empty() {
+            table.points[..len].copy_from_slice(&resample(samples, len));
+        }
+        table
+    }
+
+    /// Creates a wavetable from its points as they are, usable in
+    /// constants. Points past `MAX_POINTS` are dropped and tables
+    /// shorter than `MIN_POINTS` are padded with silence.
+    pub const fn from_points(points: &[f32]) -> Self {
+        let mut table = Wavetable {
+            points: [0.0; MAX_POINTS],
+            len: 0,
+        };
+        while table.len < points.len() && table.len < MAX_POINTS {
+            table.points[table.len] = points[table.len];
+            table.len += 1;
+        }
+        if table.len < MIN_POINTS {
+            table.len = MIN_POINTS;
+        }
+        table
+    }
+
+    /// A sine wave of `len` points.
+    pub fn sine(len: usize) -> Self {
+        let len = len.clamp(MIN_POINTS, MAX_POINTS);
+        let samples: Vec<f32> = (0..len)
+            .map(|i| (i as f32 / len as f32 * TAU).sin())
+            .collect();
+        Wavetable::new(&samples)
+    }
+
+    /// Creates a wavetable from a recorded cycle, removing its DC offset
+    /// and scaling its peak to 1.
+    pub fn from_recording(samples: &[f32]) -> Self {
+        let mut table = Wavetable::new(samples);
+        table.normalize();
+        table
+    }
+
+    pub fn points(&self) -> &[f32] {
+        &self.points[..self.len]
+    }
+
+    /// Points for editing, in -1..1.
+    pub fn points_mut(&mut self) -> &mut [f32] {
+        &mut self.points[..self.len]
+    }
+
+    /// Changes the number of points, resampling the waveform.
+    pub fn resize(&mut self, len: usize) {
+        let len = len.clamp(MIN_POINTS, MAX_POINTS);
+        let samples = resample(self.points(), len);
+        self.points[..len].copy_from_slice(&samples);
+        self.len = len;
+    }
+
+    /// Removes the DC offset and scales the peak to 1.
+    pub fn normalize(&mut self) {
+        normalize(self.points_mut());
+    }
+
+    /// Value at `phase`, 0..1 covering one period.
+    pub fn sample(&self, phase: f32) -> f32 {
+        interpolate(self.points(), phase * self.len as f32)
+    }
+
+    /// Blends two tables point by point when they have the same length,
+    /// otherwise returns the nearest one.
+    pub fn lerp(a: &Wavetable, b: &Wavetable, t: f32) -> Wavetable {
+        if a.len != b.len {
+            return if t < 0.5 { *a } else { *b };
+        }
+
+        let mut table = *a;
+        for (point, other) in table.points_mut().iter_mut().zip(b.points()) {
+            *point += (other - *point) * t;
+        }
+        table
+    }
+
+    /// Brings every point back into -1..1.
+    pub fn clamp_ranges(&mut self) {
+        for point in self.points_mut() {
+            *point = point.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+impl From<Vec<f32>> for Wavetable {
+    fn from(samples: Vec<f32>) -> Self {
+        Wavetable::new(&samples)
+    }
+}
+
+impl From<Wavetable> for Vec<f32> {
+    fn from(table: Wavetable) -> Self {
+        table.points().to_vec()
+    }
+}
+
+/// A recorded cycle of up to `MAX_CYCLE_POINTS`, too large to live in
+/// `SynthParams`. It plays in place of the wavetable it was reduced to,
+/// see `Cycle::wavetable`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    points: Vec<f32>,
+    /// Copy of the cycle stored in the params.
+    wavetable: Wavetable,
+}
+
+impl Cycle {
+    /// Creates a cycle from a recorded period, removing its DC offset
+    /// and scaling its peak to 1. Cycles longer than `MAX_CYCLE_POINTS`
+    /// are resampled to fit.
+    pub fn from_recording(samples: &[f32]) -> Self {
+        let len = samples.len().clamp(MIN_POINTS, MAX_CYCLE_POINTS);
+        let mut points = resample(samples, len);
+        normalize(&mut points);
+        Cycle {
+            wavetable: Wavetable::new(&points),
+            points,
+        }
+    }
+
+    /// Reads a short WAV file holding a single cycle, see
+    /// `from_recording`.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let (samples, _) = wav::load(path)?;
+        if samples.is_empty() {
+            anyhow::bail!("Empty wavetable");
+        }
+        Ok(Cycle::from_recording(&samples))
+    }
+
+    pub fn points(&self) -> &[f32] {
+        &self.points
+    }
+
+    /// The cycle reduced to at most `MAX_POINTS`, to be stored in
+    /// `SynthParams::wavetable`.
+    pub fn wavetable(&self) -> Wavetable {
+        self.wavetable
+    }
+
+    /// Whether the cycle is longer than the wavetable and worth handing
+    /// to the synth.
+    pub fn is_detailed(&self) -> bool {
+        self.points.len() > self.wavetable.len
+    }
+
+    /// Value at `phase`, 0..1 covering one period.
+    pub fn sample(&self, phase: f32) -> f32 {
+        interpolate(&self.points, phase * self.points.len() as f32)
+    }
+}
+
+/// One period of `samples` resampled to `len` points.
+fn resample(samples: &[f32], len: usize) -> Vec<f32> {
+    if samples.len() == len || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ratio = samples.len() as f32 / len as f32;
+    (0..len)
+        .map(|i| interpolate(samples, i as f32 * ratio))
+        .collect()
+}
+
+/// Removes the DC offset and scales the peak to 1.
+fn normalize(points: &mut [f32]) {
+    let mean = points.iter().sum::<f32>() / points.len() as f32;
+    let peak = points
+        .iter()
+        .fold(0.0f32, |peak, point| peak.max((point - mean).abs()));
+    if peak > 0.0 {
+        for point in points {
+            *point = (*point - mean) / peak;
+        }
+    }
+}
+
+/// Reads a periodic buffer at a fractional `position`, wrapping around.
+fn interpolate(samples: &[f32], position: f32) -> f32 {
+    let len = samples.len();
+    let index = position as usize;
+    let fraction = position - index as f32;
+    let a = samples[index % len];
+    let b = samples[(index + 1) % len];
+    a + (b - a) * fraction
+}